@@ -26,6 +26,8 @@ use std::ffi::OsString;
 use std::path::Path;
 use std::path::PathBuf;
 use std::sync::Arc;
+use std::sync::atomic::AtomicBool;
+use std::time::Duration;
 
 use nu_cli::NuCompleter;
 use nu_command::add_shell_command_context;
@@ -35,10 +37,43 @@ use nu_protocol::{
     engine::{EngineState, Stack, StateWorkingSet},
 };
 use reedline::Completer;
-
+use reedline::EditCommand;
+use reedline::KeyCode;
+use reedline::KeyModifiers;
+use reedline::Keybindings;
+use reedline::LineBuffer;
+use reedline::ReedlineEvent;
+use reedline::Suggestion;
+
+pub use completest::Candidate;
+pub use completest::Completion;
+pub use completest::Error;
+pub use completest::Input;
+pub use completest::InputEvent;
+pub use completest::Key;
 pub use completest::Runtime;
 pub use completest::RuntimeBuilder;
+pub use completest::RuntimeFactory;
 pub use completest::Term;
+pub use completest::Unavailable;
+pub use completest::Version;
+pub use completest::expected_path;
+pub use completest::skip_if_unavailable;
+
+const RUNTIMES: &[&dyn RuntimeFactory] = &[&NuRuntimeBuilder {}];
+
+/// Every runtime this crate provides
+pub fn all_runtimes() -> &'static [&'static dyn RuntimeFactory] {
+    RUNTIMES
+}
+
+/// Look up a runtime by its [`RuntimeBuilder::name`]
+pub fn runtime_by_name(name: &str) -> Option<&'static dyn RuntimeFactory> {
+    RUNTIMES
+        .iter()
+        .copied()
+        .find(|runtime| runtime.runtime_name() == name)
+}
 
 /// Abstract factory for [`NuRuntime`]
 #[derive(Debug)]
@@ -52,11 +87,11 @@ impl RuntimeBuilder for NuRuntimeBuilder {
         "nu"
     }
 
-    fn new(bin_root: PathBuf, home: PathBuf) -> std::io::Result<Self::Runtime> {
+    fn new(bin_root: PathBuf, home: PathBuf) -> Result<Self::Runtime, Error> {
         NuRuntime::new(bin_root, home)
     }
 
-    fn with_home(bin_root: PathBuf, home: PathBuf) -> std::io::Result<Self::Runtime> {
+    fn with_home(bin_root: PathBuf, home: PathBuf) -> Result<Self::Runtime, Error> {
         NuRuntime::with_home(bin_root, home)
     }
 }
@@ -68,11 +103,12 @@ impl RuntimeBuilder for NuRuntimeBuilder {
 pub struct NuRuntime {
     path: OsString,
     home: PathBuf,
+    timeout: Duration,
 }
 
 impl NuRuntime {
     /// Initialize a new runtime's home
-    pub fn new(bin_root: PathBuf, home: PathBuf) -> std::io::Result<Self> {
+    pub fn new(bin_root: PathBuf, home: PathBuf) -> Result<Self, Error> {
         std::fs::create_dir_all(&home)?;
 
         let config = "";
@@ -84,11 +120,15 @@ impl NuRuntime {
     }
 
     /// Reuse an existing runtime's home
-    pub fn with_home(bin_root: PathBuf, home: PathBuf) -> std::io::Result<Self> {
+    pub fn with_home(bin_root: PathBuf, home: PathBuf) -> Result<Self, Error> {
         let bin_root = dunce::canonicalize(bin_root)?;
         let home = dunce::canonicalize(home)?;
         let path = build_path(bin_root);
-        Ok(Self { path, home })
+        Ok(Self {
+            path,
+            home,
+            timeout: Duration::from_secs(10),
+        })
     }
 
     /// Location of the runtime's home directory
@@ -96,21 +136,58 @@ impl NuRuntime {
         &self.home
     }
 
+    /// Version of the embedded nushell, from its `version` command
+    pub fn version(&self) -> Result<Version, Error> {
+        use nu_protocol::engine::Command as _;
+
+        let (engine_state, mut stack) = new_engine(&self.path, &self.home)?;
+        let span = nu_protocol::Span::unknown();
+        let call = nu_protocol::ast::Call::new(span);
+        let data = nu_cmd_lang::Version
+            .run(
+                &engine_state,
+                &mut stack,
+                &call,
+                nu_protocol::PipelineData::empty(),
+            )
+            .map_err(std::io::Error::other)?;
+        let version = data
+            .into_value(span)
+            .get_data_by_key("version")
+            .ok_or_else(|| std::io::Error::other("nushell has no `version`"))?
+            .as_string()
+            .map_err(std::io::Error::other)?;
+        Ok(Version::new(version))
+    }
+
     /// Register a completion script
-    pub fn register(&mut self, name: &str, content: &str) -> std::io::Result<()> {
+    pub fn register(&mut self, name: &str, content: &str) -> Result<(), Error> {
         let path = self
             .home
             .join(format!(".config/nushell/completions/{name}.nu"));
         std::fs::create_dir_all(path.parent().expect("path created with a parent"))?;
-        std::fs::write(path, content)
+        std::fs::write(path, content)?;
+        Ok(())
     }
 
-    /// Get the output from typing `input` into the shell
-    pub fn complete(&mut self, input: &str, term: &Term) -> std::io::Result<String> {
-        use std::fmt::Write as _;
+    /// How long a completer may run before it is interrupted
+    pub fn get_timeout(&self) -> Duration {
+        self.timeout
+    }
+
+    /// Change how long a completer may run before it is interrupted
+    ///
+    /// Nushell commands check for interruption cooperatively, like with `Ctrl-C`.
+    pub fn set_timeout(&mut self, timeout: Duration) {
+        self.timeout = timeout;
+    }
 
-        let input = input.split_once('\t').unwrap_or((input, "")).0;
+    /// Get the output from typing `input` into the shell
+    pub fn complete(&mut self, input: &str, term: &Term) -> Result<String, Error> {
+        self.complete_input(&Input::from(input), term)
+    }
 
+    fn suggest(&self, input: &str, pos: usize) -> Result<Vec<Suggestion>, Error> {
         let completion_root = self.home.join(".config/nushell/completions");
         let mut completers = std::collections::BTreeMap::new();
         for entry in std::fs::read_dir(completion_root)? {
@@ -125,19 +202,203 @@ impl NuRuntime {
                 completers.insert(stem.to_owned(), content);
             }
         }
-        let mut completer = external_completion(&self.path, &self.home, &completers)?;
+        let interrupt = Arc::new(AtomicBool::new(false));
+        let mut completer =
+            external_completion(&self.path, &self.home, &completers, interrupt.clone())?;
+
+        let suggestions =
+            interrupt_after(self.timeout, &interrupt, || completer.complete(input, pos));
+        if interrupt.load(std::sync::atomic::Ordering::SeqCst) {
+            return Err(Error::Timeout {
+                screen: format!("% {input}\n"),
+            });
+        }
+        Ok(suggestions)
+    }
+}
+
+impl Runtime for NuRuntime {
+    fn home(&self) -> &Path {
+        self.home()
+    }
+
+    fn version(&self) -> Result<Version, Error> {
+        self.version()
+    }
+
+    fn register(&mut self, name: &str, content: &str) -> Result<(), Error> {
+        self.register(name, content)
+    }
+
+    /// Get the output from typing `input` into the shell
+    ///
+    /// Like nushell's completion menu, this lists the candidates for the first `Tab`.  If `input`
+    /// never presses `Tab`, it completes at the end of `input`.
+    fn complete_input(&mut self, input: &Input, term: &Term) -> Result<String, Error> {
+        let mut editor = Editor::new();
+        for event in input.get_events() {
+            editor.event(event, |buffer, pos| self.suggest(buffer, pos))?;
+            if editor.listed.is_some() {
+                break;
+            }
+        }
+        editor.finish(|buffer, pos| self.suggest(buffer, pos))?;
+        Ok(editor.screen(term))
+    }
+
+    /// Get the candidates offered by the shell for `input`, including keys besides `Tab`
+    ///
+    /// Keys are applied like reedline's default emacs keybindings.  When `Tab` offers more than one
+    /// candidate, it opens the completion menu:
+    /// - `Tab`, `Down` and `Right` select the next candidate
+    /// - `ShiftTab`, `Up` and `Left` select the previous candidate
+    /// - `Enter` accepts the selected candidate
+    /// - `Escape` closes the menu
+    ///
+    /// If `input` never presses `Tab`, it completes at the end of `input`.  The
+    /// [screen][Completion::get_screen] lists the candidates for the last `Tab`.
+    fn completion_input(&mut self, input: &Input, term: &Term) -> Result<Completion, Error> {
+        let mut editor = Editor::new();
+        for event in input.get_events() {
+            editor.event(event, |buffer, pos| self.suggest(buffer, pos))?;
+        }
+        editor.finish(|buffer, pos| self.suggest(buffer, pos))?;
+
+        let screen = editor.screen(term);
+        let highlighted = editor
+            .menu
+            .map(|selected| editor.suggestions[selected].value.clone());
+        let candidates = editor.suggestions.into_iter().map(|suggestion| {
+            let candidate = Candidate::new(suggestion.value);
+            match suggestion.description {
+                Some(description) => candidate.description(description.trim_end_matches('\n')),
+                None => candidate,
+            }
+        });
+
+        let completion = Completion::new(screen)
+            .buffer(editor.line.get_buffer())
+            .cursor(editor.line.insertion_point())
+            .candidates(candidates);
+        Ok(match highlighted {
+            Some(highlighted) => completion.highlighted(highlighted),
+            None => completion,
+        })
+    }
+}
+
+/// Command-line being typed, replayed like reedline with its default emacs keybindings
+struct Editor {
+    keybindings: Keybindings,
+    line: LineBuffer,
+    /// Command-line the last `Tab` listed [`Editor::suggestions`] for
+    listed: Option<String>,
+    suggestions: Vec<Suggestion>,
+    /// Selected suggestion while the completion menu is open
+    menu: Option<usize>,
+}
+
+impl Editor {
+    fn new() -> Self {
+        Self {
+            keybindings: reedline::default_emacs_keybindings(),
+            line: LineBuffer::new(),
+            listed: None,
+            suggestions: Vec::new(),
+            menu: None,
+        }
+    }
+
+    /// Apply `event`, calling `suggest` with the command-line and cursor when it presses `Tab`
+    fn event(
+        &mut self,
+        event: &InputEvent,
+        suggest: impl FnOnce(&str, usize) -> Result<Vec<Suggestion>, Error>,
+    ) -> Result<(), Error> {
+        match (event, self.menu) {
+            (InputEvent::Key(Key::Tab), None) => {
+                self.listed = Some(self.line.get_buffer().to_owned());
+                self.suggestions = suggest(self.line.get_buffer(), self.line.insertion_point())?;
+                match self.suggestions.as_slice() {
+                    [] => {}
+                    [suggestion] => accept(&mut self.line, suggestion),
+                    _ => self.menu = Some(0),
+                }
+            }
+            (InputEvent::Key(key), Some(selected)) => {
+                let len = self.suggestions.len();
+                match key {
+                    Key::Tab | Key::Down | Key::Right => self.menu = Some((selected + 1) % len),
+                    Key::ShiftTab | Key::Up | Key::Left => {
+                        self.menu = Some((selected + len - 1) % len);
+                    }
+                    Key::Enter => {
+                        accept(&mut self.line, &self.suggestions[selected]);
+                        self.menu = None;
+                    }
+                    Key::Escape => self.menu = None,
+                    _ => {
+                        return Err(Error::Unsupported(format!(
+                            "{key:?} in nushell's completion menu"
+                        )));
+                    }
+                }
+            }
+            (InputEvent::Key(key), None) => edit_key(&self.keybindings, &mut self.line, *key)?,
+            (InputEvent::Text(text), None) => self.line.insert_str(text),
+            (InputEvent::Cursor(offset), None) => {
+                let buffer = self.line.get_buffer();
+                let mut offset = (*offset).min(buffer.len());
+                while !buffer.is_char_boundary(offset) {
+                    offset -= 1;
+                }
+                self.line.set_insertion_point(offset);
+            }
+            (InputEvent::Cursor(_), Some(_)) => {
+                return Err(Error::Unsupported(
+                    "moving the cursor in nushell's completion menu".to_owned(),
+                ));
+            }
+            (InputEvent::Text(_), Some(_)) => {
+                return Err(Error::Unsupported(
+                    "typing in nushell's completion menu".to_owned(),
+                ));
+            }
+            _ => return Err(Error::Unsupported(format!("{event:?} in nushell"))),
+        }
+        Ok(())
+    }
+
+    /// Complete at the cursor if `Tab` was never pressed
+    fn finish(
+        &mut self,
+        suggest: impl FnOnce(&str, usize) -> Result<Vec<Suggestion>, Error>,
+    ) -> Result<(), Error> {
+        if self.listed.is_some() {
+            return Ok(());
+        }
+        self.event(&InputEvent::Key(Key::Tab), suggest)?;
+        self.menu = None;
+        Ok(())
+    }
 
-        let suggestions = completer.complete(input, input.len());
+    /// Render the listed command-line and its suggestions
+    fn screen(&self, term: &Term) -> String {
+        use std::fmt::Write as _;
 
         let mut max_value_len = 0;
-        for suggestion in &suggestions {
+        for suggestion in &self.suggestions {
             max_value_len = suggestion.value.len().max(max_value_len);
         }
         let spacer = "    ";
 
         let mut buffer = String::new();
-        let _ = writeln!(&mut buffer, "% {input}");
-        for suggestion in &suggestions {
+        let _ = writeln!(
+            &mut buffer,
+            "% {}",
+            self.listed.as_deref().unwrap_or_default()
+        );
+        for suggestion in &self.suggestions {
             let value = &suggestion.value;
             let max_descr_len = (term.get_width() as usize) - max_value_len - spacer.len();
             let descr = suggestion
@@ -149,39 +410,115 @@ impl NuRuntime {
             let descr = &descr[0..max_descr_len.min(descr.len())];
             let _ = writeln!(&mut buffer, "{value}{spacer}{descr}");
         }
+        buffer
+    }
+}
 
-        Ok(buffer)
+/// Press `key` like reedline would with its default emacs keybindings
+fn edit_key(keybindings: &Keybindings, line: &mut LineBuffer, key: Key) -> Result<(), Error> {
+    let event =
+        key_code(key).and_then(|(modifiers, code)| keybindings.find_binding(modifiers, code));
+    if event.is_some_and(|event| apply_event(line, &event)) {
+        Ok(())
+    } else {
+        Err(Error::Unsupported(format!("{key:?} in nushell")))
     }
 }
 
-impl Runtime for NuRuntime {
-    fn home(&self) -> &Path {
-        self.home()
+/// Replace the word being completed with `suggestion`
+fn accept(line: &mut LineBuffer, suggestion: &Suggestion) {
+    let span = suggestion.span.start..suggestion.span.end;
+    if line.get_buffer().get(span.clone()).is_none() {
+        return;
     }
+    line.replace_range(span.clone(), &suggestion.value);
+    line.set_insertion_point(span.start + suggestion.value.len());
+    if suggestion.append_whitespace {
+        line.insert_char(' ');
+    }
+}
 
-    fn register(&mut self, name: &str, content: &str) -> std::io::Result<()> {
-        self.register(name, content)
+fn key_code(key: Key) -> Option<(KeyModifiers, KeyCode)> {
+    let code = match key {
+        Key::Tab => (KeyModifiers::NONE, KeyCode::Tab),
+        Key::ShiftTab => (KeyModifiers::SHIFT, KeyCode::BackTab),
+        Key::Enter => (KeyModifiers::NONE, KeyCode::Enter),
+        Key::Escape => (KeyModifiers::NONE, KeyCode::Esc),
+        Key::Backspace => (KeyModifiers::NONE, KeyCode::Backspace),
+        Key::Up => (KeyModifiers::NONE, KeyCode::Up),
+        Key::Down => (KeyModifiers::NONE, KeyCode::Down),
+        Key::Left => (KeyModifiers::NONE, KeyCode::Left),
+        Key::Right => (KeyModifiers::NONE, KeyCode::Right),
+        Key::Home => (KeyModifiers::NONE, KeyCode::Home),
+        Key::End => (KeyModifiers::NONE, KeyCode::End),
+        Key::Ctrl(c) => (KeyModifiers::CONTROL, KeyCode::Char(c.to_ascii_lowercase())),
+        _ => return None,
+    };
+    Some(code)
+}
+
+/// Apply the line edits in `event`, returning whether it had any
+fn apply_event(line: &mut LineBuffer, event: &ReedlineEvent) -> bool {
+    match event {
+        ReedlineEvent::Edit(commands) => commands.iter().all(|command| apply_edit(line, command)),
+        ReedlineEvent::UntilFound(events) => events.iter().any(|event| apply_event(line, event)),
+        ReedlineEvent::Left => {
+            line.move_left();
+            true
+        }
+        ReedlineEvent::Right => {
+            line.move_right();
+            true
+        }
+        _ => false,
     }
+}
 
-    fn complete(&mut self, input: &str, term: &Term) -> std::io::Result<String> {
-        self.complete(input, term)
+fn apply_edit(line: &mut LineBuffer, command: &EditCommand) -> bool {
+    match command {
+        EditCommand::MoveToStart => line.move_to_start(),
+        EditCommand::MoveToLineStart => line.move_to_line_start(),
+        EditCommand::MoveToEnd => line.move_to_end(),
+        EditCommand::MoveToLineEnd => line.move_to_line_end(),
+        EditCommand::MoveLeft => line.move_left(),
+        EditCommand::MoveRight => line.move_right(),
+        EditCommand::MoveWordLeft => line.move_word_left(),
+        EditCommand::MoveWordRight => line.move_word_right(),
+        EditCommand::InsertChar(c) => line.insert_char(*c),
+        EditCommand::InsertString(text) => line.insert_str(text),
+        EditCommand::Backspace => line.delete_left_grapheme(),
+        EditCommand::Delete => line.delete_right_grapheme(),
+        EditCommand::BackspaceWord | EditCommand::CutWordLeft => line.delete_word_left(),
+        EditCommand::DeleteWord | EditCommand::CutWordRight => line.delete_word_right(),
+        EditCommand::Clear => line.clear(),
+        EditCommand::ClearToLineEnd | EditCommand::CutToLineEnd => line.clear_to_line_end(),
+        EditCommand::CutToEnd => line.clear_to_end(),
+        EditCommand::CutFromStart | EditCommand::CutFromLineStart => {
+            line.clear_to_insertion_point();
+        }
+        _ => return false,
     }
+    true
 }
 
 fn external_completion(
     path: &OsStr,
     home: &Path,
     completers: &std::collections::BTreeMap<String, String>,
-) -> std::io::Result<NuCompleter> {
+    interrupt: Arc<AtomicBool>,
+) -> Result<NuCompleter, Error> {
     // Create a new engine
     let (mut engine_state, mut stack) = new_engine(path, home)?;
+    engine_state.ctrlc = Some(interrupt);
 
     for completer in completers.values() {
         let (_, delta) = {
             let mut working_set = StateWorkingSet::new(&engine_state);
             let block = parse(&mut working_set, None, completer.as_bytes(), false);
             if !working_set.parse_errors.is_empty() {
-                return Err(std::io::Error::other(working_set.parse_errors.remove(0)));
+                return Err(Error::Script(
+                    working_set.parse_errors.remove(0).to_string(),
+                ));
             }
 
             (block, working_set.render())
@@ -189,7 +526,7 @@ fn external_completion(
 
         engine_state
             .merge_delta(delta)
-            .map_err(std::io::Error::other)?;
+            .map_err(|err| Error::Script(err.to_string()))?;
     }
 
     // Merge environment into the permanent state
@@ -198,7 +535,7 @@ fn external_completion(
         .map_err(std::io::Error::other)?;
 
     if engine_state.num_blocks() == 0 {
-        return Err(std::io::Error::other("completer not registered"));
+        return Err(Error::Script("completer not registered".to_owned()));
     }
     let latest_block_id = engine_state.num_blocks() - 1;
 
@@ -211,8 +548,24 @@ fn external_completion(
     Ok(NuCompleter::new(Arc::new(engine_state), stack))
 }
 
+/// Run `f`, asking nushell to interrupt it after `timeout`
+fn interrupt_after<T>(timeout: Duration, interrupt: &AtomicBool, f: impl FnOnce() -> T) -> T {
+    let (done, finished) = std::sync::mpsc::channel::<()>();
+    std::thread::scope(|scope| {
+        scope.spawn(move || {
+            if let Err(std::sync::mpsc::RecvTimeoutError::Timeout) = finished.recv_timeout(timeout)
+            {
+                interrupt.store(true, std::sync::atomic::Ordering::SeqCst);
+            }
+        });
+        let result = f();
+        drop(done);
+        result
+    })
+}
+
 /// creates a new engine with the current path into the completions fixtures folder
-fn new_engine(path: &OsStr, home: &Path) -> std::io::Result<(EngineState, Stack)> {
+fn new_engine(path: &OsStr, home: &Path) -> Result<(EngineState, Stack), Error> {
     let mut pwd = home
         .to_owned()
         .into_os_string()
@@ -272,3 +625,130 @@ fn build_path(bin_root: PathBuf) -> OsString {
     }
     path
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn suggestion(value: &str, start: usize, end: usize) -> Suggestion {
+        Suggestion {
+            value: value.to_owned(),
+            span: reedline::Span { start, end },
+            append_whitespace: true,
+            ..Default::default()
+        }
+    }
+
+    /// Replay `input`, offering `suggestions` for every `Tab`
+    fn replay(input: &Input, suggestions: &[Suggestion]) -> Result<Editor, Error> {
+        let mut editor = Editor::new();
+        for event in input.get_events() {
+            editor.event(event, |_, _| Ok(suggestions.to_vec()))?;
+        }
+        editor.finish(|_, _| Ok(suggestions.to_vec()))?;
+        Ok(editor)
+    }
+
+    #[test]
+    fn accept_replaces_span() {
+        let mut line = LineBuffer::new();
+        line.insert_str("foo --he bar");
+        accept(&mut line, &suggestion("--help", 4, 8));
+        assert_eq!(line.get_buffer(), "foo --help  bar");
+        assert_eq!(line.insertion_point(), 11);
+
+        let mut line = LineBuffer::new();
+        line.insert_str("foo");
+        accept(&mut line, &suggestion("--help", 4, 8));
+        assert_eq!(line.get_buffer(), "foo");
+    }
+
+    #[test]
+    fn edit_key_emacs() {
+        let keybindings = reedline::default_emacs_keybindings();
+        let mut line = LineBuffer::new();
+        line.insert_str("foo bar");
+
+        edit_key(&keybindings, &mut line, Key::Left).unwrap();
+        assert_eq!(line.insertion_point(), 6);
+        edit_key(&keybindings, &mut line, Key::Ctrl('a')).unwrap();
+        assert_eq!(line.insertion_point(), 0);
+        edit_key(&keybindings, &mut line, Key::End).unwrap();
+        edit_key(&keybindings, &mut line, Key::Backspace).unwrap();
+        assert_eq!(line.get_buffer(), "foo ba");
+        edit_key(&keybindings, &mut line, Key::Ctrl('w')).unwrap();
+        assert_eq!(line.get_buffer(), "foo ");
+
+        assert!(matches!(
+            edit_key(&keybindings, &mut line, Key::Enter),
+            Err(Error::Unsupported(_))
+        ));
+    }
+
+    #[test]
+    fn single_suggestion_is_accepted() {
+        let suggestions = [suggestion("--help", 4, 8)];
+        let editor = replay(&Input::from("foo --he\t"), &suggestions).unwrap();
+        assert_eq!(editor.line.get_buffer(), "foo --help ");
+        assert_eq!(editor.listed.as_deref(), Some("foo --he"));
+        assert_eq!(editor.menu, None);
+
+        let editor = replay(&Input::from("foo --he"), &suggestions).unwrap();
+        assert_eq!(editor.line.get_buffer(), "foo --help ");
+        assert_eq!(editor.listed.as_deref(), Some("foo --he"));
+    }
+
+    #[test]
+    fn menu_selection() {
+        let suggestions = [
+            suggestion("--help", 4, 6),
+            suggestion("--hidden", 4, 6),
+            suggestion("--hold", 4, 6),
+        ];
+        let menu = |input: Input| replay(&input, &suggestions).unwrap().menu;
+        let open = || Input::new().text("foo --").key(Key::Tab);
+
+        assert_eq!(menu(open()), Some(0));
+        assert_eq!(menu(open().key(Key::Tab)), Some(1));
+        assert_eq!(menu(open().key(Key::Tab).key(Key::Down)), Some(2));
+        assert_eq!(
+            menu(open().key(Key::Tab).key(Key::Tab).key(Key::Right)),
+            Some(0)
+        );
+        assert_eq!(menu(open().key(Key::ShiftTab)), Some(2));
+        assert_eq!(menu(open().key(Key::Up).key(Key::Left)), Some(1));
+        assert_eq!(menu(open().key(Key::Escape)), None);
+
+        let editor = replay(&open().key(Key::Tab).key(Key::Enter), &suggestions).unwrap();
+        assert_eq!(editor.line.get_buffer(), "foo --hidden ");
+        assert_eq!(editor.menu, None);
+
+        let editor = replay(&open().key(Key::Escape), &suggestions).unwrap();
+        assert_eq!(editor.line.get_buffer(), "foo --");
+
+        assert!(matches!(
+            replay(&open().text("h"), &suggestions),
+            Err(Error::Unsupported(_))
+        ));
+        assert!(matches!(
+            replay(&open().key(Key::Home), &suggestions),
+            Err(Error::Unsupported(_))
+        ));
+    }
+
+    #[test]
+    fn screen_lists_suggestions() {
+        let suggestions = [
+            Suggestion {
+                description: Some("Print help\n".to_owned()),
+                ..suggestion("--help", 4, 6)
+            },
+            suggestion("--hold", 4, 6),
+        ];
+        let editor = replay(&Input::from("foo --\t\t"), &suggestions).unwrap();
+        assert_eq!(
+            editor.screen(&Term::new()),
+            "% foo --\n--help    Print help\n--hold\n"
+        );
+    }
+}
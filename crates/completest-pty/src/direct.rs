@@ -0,0 +1,324 @@
+//! Support for runtimes that ask the shell for completions without a terminal
+
+use std::process::Command;
+use std::process::Stdio;
+use std::sync::mpsc::Receiver;
+use std::sync::mpsc::RecvTimeoutError;
+use std::time::Duration;
+use std::time::Instant;
+
+use crate::Candidate;
+use crate::Completion;
+use crate::Error;
+use crate::Input;
+use crate::InputEvent;
+use crate::Key;
+use crate::Version;
+use crate::session::floor_char_boundary;
+
+// How often to check whether the shell has exited
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// What sets apart the shells asked for completions without a terminal
+///
+/// [`direct_runtime!`] provides the rest of the runtime from this.
+pub(crate) trait DirectShell {
+    /// Ask the shell for the [`Completion`] of `line`, with the cursor at byte `cursor`
+    fn completion(&self, line: String, cursor: usize) -> Result<Completion, Error>;
+}
+
+/// Provide the timeout accessors and [`Runtime`][crate::Runtime] for a [`DirectShell`]
+///
+/// The runtime is expected to have a `timeout: Duration` field and inherent `home`, `version`,
+/// and `register` methods.
+macro_rules! direct_runtime {
+    ($runtime:ty) => {
+        impl $runtime {
+            /// How long the shell may take to complete before it is killed
+            pub fn get_timeout(&self) -> Duration {
+                self.timeout
+            }
+
+            /// Change how long the shell may take to complete before it is killed
+            pub fn set_timeout(&mut self, timeout: Duration) {
+                self.timeout = timeout;
+            }
+        }
+
+        impl Runtime for $runtime {
+            fn home(&self) -> &std::path::Path {
+                self.home()
+            }
+
+            fn version(&self) -> Result<Version, Error> {
+                self.version()
+            }
+
+            fn register(&mut self, name: &str, content: &str) -> Result<(), Error> {
+                self.register(name, content)
+            }
+
+            /// Get the candidates offered by the shell for `input`, including keys besides `Tab`
+            ///
+            /// Only typing and moving the cursor can be replayed, up to the first `Tab`.  The
+            /// command-line is reported as typed.
+            fn completion_input(
+                &mut self,
+                input: &Input,
+                _term: &Term,
+            ) -> Result<Completion, Error> {
+                let (line, cursor) = crate::direct::line(input)?;
+                crate::direct::DirectShell::completion(self, line, cursor)
+            }
+        }
+    };
+}
+
+/// Command-line and byte offset of the cursor when `input` asks for completions
+///
+/// Completions are requested by the first `Tab`, or at the end of `input` if there is none.
+/// Without a terminal, only typing and moving the cursor can be replayed before that.
+pub(crate) fn line(input: &Input) -> Result<(String, usize), Error> {
+    let mut line = String::new();
+    let mut cursor = 0;
+    let mut completed = false;
+    for event in input.get_events() {
+        match (event, completed) {
+            (InputEvent::Key(Key::Tab), _) => completed = true,
+            (InputEvent::Text(text), false) => {
+                line.insert_str(cursor, text);
+                cursor += text.len();
+            }
+            (InputEvent::Cursor(offset), false) => {
+                cursor = floor_char_boundary(&line, *offset);
+            }
+            (_, true) => {
+                return Err(Error::Unsupported(format!(
+                    "{event:?} after completing without a terminal"
+                )));
+            }
+            (_, false) => {
+                return Err(Error::Unsupported(format!("{event:?} without a terminal")));
+            }
+        }
+    }
+    Ok((line, cursor))
+}
+
+/// Run `command` to completion, returning its `stdout`
+///
+/// `stderr` is reported as a [`Error::Script`] if the shell fails.
+pub(crate) fn run(mut command: Command, timeout: Duration) -> Result<String, Error> {
+    use std::os::unix::process::CommandExt as _;
+
+    let shell = command.get_program().to_string_lossy().into_owned();
+    let deadline = Instant::now() + timeout;
+    let mut child = command
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        // Let a timeout take down anything the completion script started
+        .process_group(0)
+        .spawn()
+        .map_err(|err| match err.kind() {
+            std::io::ErrorKind::NotFound => Error::ShellNotFound(shell),
+            _ => Error::Io(err),
+        })?;
+    let stdout = drain(child.stdout.take().expect("stdout is piped"));
+    let stderr = drain(child.stderr.take().expect("stderr is piped"));
+    let pgid = nix::unistd::Pid::from_raw(child.id() as i32);
+
+    let status = loop {
+        if let Some(status) = child.try_wait()? {
+            break status;
+        }
+        if deadline <= Instant::now() {
+            let _ = nix::sys::signal::killpg(pgid, nix::sys::signal::Signal::SIGKILL);
+            let _ = child.wait();
+            let screen = stdout.recv_timeout(POLL_INTERVAL).unwrap_or_default();
+            return Err(Error::Timeout { screen });
+        }
+        std::thread::sleep(POLL_INTERVAL);
+    };
+    // Anything the completion script left in the background holds the pipes open
+    let _ = nix::sys::signal::killpg(pgid, nix::sys::signal::Signal::SIGKILL);
+
+    let stdout = collect(&stdout, deadline)?;
+    let stderr = collect(&stderr, deadline)?;
+    if status.success() {
+        Ok(stdout)
+    } else {
+        Err(Error::Script(stderr.trim().to_owned()))
+    }
+}
+
+/// Version the shell reports on the first line of `command`'s output
+pub(crate) fn version(command: Command, timeout: Duration) -> Result<Version, Error> {
+    let output = run(command, timeout)?;
+    let version = output
+        .lines()
+        .map(str::trim)
+        .find(|line| !line.is_empty())
+        .unwrap_or_default();
+    Ok(Version::new(version))
+}
+
+/// Split `output` into records of `N` NUL-terminated fields
+pub(crate) fn records<const N: usize>(output: &str) -> Vec<[&str; N]> {
+    let fields: Vec<_> = output.split_terminator('\0').collect();
+    fields
+        .chunks_exact(N)
+        .filter_map(|record| record.try_into().ok())
+        .collect()
+}
+
+/// Candidate for `value`, keeping `display` if the shell shows it differently
+pub(crate) fn candidate(value: &str, display: &str) -> Candidate {
+    let candidate = Candidate::new(value);
+    if !display.is_empty() && display != value {
+        candidate.display(display)
+    } else {
+        candidate
+    }
+}
+
+/// List `candidates` one per line, as the shell would display them
+pub(crate) fn screen(candidates: &[Candidate]) -> String {
+    candidates
+        .iter()
+        .map(|candidate| {
+            let display = candidate.get_display().unwrap_or(candidate.get_value());
+            format!("{display}\n")
+        })
+        .collect()
+}
+
+/// Read `stream` to the end without blocking the caller
+fn drain(mut stream: impl std::io::Read + Send + 'static) -> Receiver<String> {
+    let (snd, rcv) = std::sync::mpsc::channel();
+    std::thread::spawn(move || {
+        let mut buf = Vec::new();
+        let _ = stream.read_to_end(&mut buf);
+        let _ = snd.send(String::from_utf8_lossy(&buf).into_owned());
+    });
+    rcv
+}
+
+/// Wait for a [`drain`]ed stream, giving up at `deadline` in case something that escaped the
+/// process group still holds it open
+fn collect(stream: &Receiver<String>, deadline: Instant) -> Result<String, Error> {
+    let remaining = deadline.saturating_duration_since(Instant::now());
+    match stream.recv_timeout(remaining) {
+        Ok(output) => Ok(output),
+        Err(RecvTimeoutError::Disconnected) => Ok(String::new()),
+        Err(RecvTimeoutError::Timeout) => Err(Error::Timeout {
+            screen: String::new(),
+        }),
+    }
+}
+
+/// Characters bash splits words on besides whitespace, from the default `COMP_WORDBREAKS`
+///
+/// Quotes are left out as the command-line isn't unquoted.
+const WORDBREAKS: &[char] = &['>', '<', '=', ';', '|', '&', '(', ':'];
+
+/// Split `line` into `COMP_WORDS`, returning them with `COMP_CWORD` for `cursor` and the word
+/// being completed, up to the cursor
+///
+/// Like readline, runs of [`WORDBREAKS`] are words of their own.
+pub(crate) fn bash_words(line: &str, cursor: usize) -> (Vec<&str>, usize, &str) {
+    let mut spans: Vec<(usize, usize)> = Vec::new();
+    let mut current: Option<(usize, bool)> = None;
+    for (i, c) in line.char_indices() {
+        let kind = (!c.is_whitespace()).then(|| WORDBREAKS.contains(&c));
+        match (current, kind) {
+            (Some((_, breaks)), Some(kind)) if breaks == kind => {}
+            (Some((start, _)), _) => {
+                spans.push((start, i));
+                current = kind.map(|kind| (i, kind));
+            }
+            (None, _) => current = kind.map(|kind| (i, kind)),
+        }
+    }
+    if let Some((start, _)) = current {
+        spans.push((start, line.len()));
+    }
+
+    let cword = spans
+        .iter()
+        .position(|(start, end)| *start <= cursor && cursor <= *end);
+    let cword = match cword {
+        Some(cword) => cword,
+        None => {
+            // Completing a new word
+            let cword = spans.iter().filter(|(start, _)| *start < cursor).count();
+            spans.insert(cword, (cursor, cursor));
+            cword
+        }
+    };
+    let cur = &line[spans[cword].0..cursor];
+    let words = spans
+        .into_iter()
+        .map(|(start, end)| &line[start..end])
+        .collect();
+    (words, cword, cur)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn records_of_fields() {
+        assert_eq!(records("a\0A\0b\0\0"), [["a", "A"], ["b", ""]]);
+        assert_eq!(records::<2>("a\0A\0b\0"), [["a", "A"]]);
+        assert_eq!(records::<1>(""), [] as [[&str; 1]; 0]);
+    }
+
+    #[test]
+    fn screen_shows_display() {
+        let candidates = [candidate("a", "A"), candidate("b", ""), candidate("c", "c")];
+        assert_eq!(candidates[2].get_display(), None);
+        assert_eq!(screen(&candidates), "A\nb\nc\n");
+    }
+
+    #[test]
+    fn bash_words_end_of_word() {
+        assert_eq!(bash_words("foo --co", 8), (vec!["foo", "--co"], 1, "--co"));
+    }
+
+    #[test]
+    fn bash_words_new_word() {
+        assert_eq!(bash_words("foo ", 4), (vec!["foo", ""], 1, ""));
+        assert_eq!(bash_words("foo  bar", 4), (vec!["foo", "", "bar"], 1, ""));
+    }
+
+    #[test]
+    fn bash_words_mid_word() {
+        assert_eq!(
+            bash_words("foo --color bar", 6),
+            (vec!["foo", "--color", "bar"], 1, "--")
+        );
+    }
+
+    #[test]
+    fn bash_words_wordbreaks() {
+        assert_eq!(
+            bash_words("foo --opt=val", 13),
+            (vec!["foo", "--opt", "=", "val"], 3, "val")
+        );
+        assert_eq!(
+            bash_words("foo --opt=", 10),
+            (vec!["foo", "--opt", "="], 2, "=")
+        );
+        assert_eq!(
+            bash_words("foo a&&b", 8),
+            (vec!["foo", "a", "&&", "b"], 3, "b")
+        );
+    }
+
+    #[test]
+    fn bash_words_empty_line() {
+        assert_eq!(bash_words("", 0), (vec![""], 0, ""));
+    }
+}
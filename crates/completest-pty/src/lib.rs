@@ -22,19 +22,72 @@
 #![warn(clippy::print_stdout)]
 #![cfg(unix)]
 
+#[macro_use]
+mod direct;
+mod parse;
+#[macro_use]
+mod pty;
+mod session;
+mod wait;
+
 use std::ffi::OsStr;
 use std::ffi::OsString;
-use std::io::Read as _;
 use std::io::Write as _;
 use std::path::PathBuf;
 use std::process::Command;
 use std::time::Duration;
 
-use ptyprocess::PtyProcess;
-
+pub use completest::Candidate;
+pub use completest::Completion;
+pub use completest::Error;
+pub use completest::Input;
+pub use completest::InputEvent;
+pub use completest::Key;
 pub use completest::Runtime;
 pub use completest::RuntimeBuilder;
+pub use completest::RuntimeFactory;
 pub use completest::Term;
+pub use completest::Unavailable;
+pub use completest::Version;
+pub use completest::expected_path;
+pub use completest::skip_if_unavailable;
+pub use session::Session;
+pub use vt100::Screen;
+pub use wait::WaitStrategy;
+
+use direct::DirectShell;
+use pty::PtyShell;
+
+const RUNTIMES: &[&dyn RuntimeFactory] = &[
+    &ZshRuntimeBuilder {},
+    &ZshBashcompinitRuntimeBuilder {},
+    &ZshZptyRuntimeBuilder {},
+    &BashRuntimeBuilder {},
+    &BashCompgenRuntimeBuilder {},
+    &FishRuntimeBuilder {},
+    &FishDirectRuntimeBuilder {},
+    &ElvishRuntimeBuilder {},
+    &ElvishDirectRuntimeBuilder {},
+    &PwshRuntimeBuilder {},
+    &XonshRuntimeBuilder {},
+    &TcshRuntimeBuilder {},
+    &KshRuntimeBuilder {},
+    &OshRuntimeBuilder {},
+    &NuPtyRuntimeBuilder {},
+];
+
+/// Every runtime this crate provides
+pub fn all_runtimes() -> &'static [&'static dyn RuntimeFactory] {
+    RUNTIMES
+}
+
+/// Look up a runtime by its [`RuntimeBuilder::name`]
+pub fn runtime_by_name(name: &str) -> Option<&'static dyn RuntimeFactory> {
+    RUNTIMES
+        .iter()
+        .copied()
+        .find(|runtime| runtime.runtime_name() == name)
+}
 
 /// Abstract factory for [`ZshRuntime`]
 #[derive(Debug)]
@@ -48,11 +101,15 @@ impl RuntimeBuilder for ZshRuntimeBuilder {
         "zsh"
     }
 
-    fn new(bin_root: PathBuf, home: PathBuf) -> std::io::Result<Self::Runtime> {
+    fn is_available() -> Result<(), Unavailable> {
+        find_binary("zsh")
+    }
+
+    fn new(bin_root: PathBuf, home: PathBuf) -> Result<Self::Runtime, Error> {
         ZshRuntime::new(bin_root, home)
     }
 
-    fn with_home(bin_root: PathBuf, home: PathBuf) -> std::io::Result<Self::Runtime> {
+    fn with_home(bin_root: PathBuf, home: PathBuf) -> Result<Self::Runtime, Error> {
         ZshRuntime::with_home(bin_root, home)
     }
 }
@@ -63,19 +120,36 @@ impl RuntimeBuilder for ZshRuntimeBuilder {
 pub struct ZshRuntime {
     path: OsString,
     home: PathBuf,
-    timeout: Duration,
+    wait: WaitStrategy,
+    menu: bool,
 }
 
 impl ZshRuntime {
     /// Initialize a new runtime's home
-    pub fn new(bin_root: PathBuf, home: PathBuf) -> std::io::Result<Self> {
+    pub fn new(bin_root: PathBuf, home: PathBuf) -> Result<Self, Error> {
         std::fs::create_dir_all(&home)?;
 
         let config_path = home.join(".zshenv");
         let config = "\
 fpath=($fpath $ZDOTDIR/zsh)
 autoload -U +X compinit && compinit -u # bypass compaudit security checking
-precmd_functions=\"\"  # avoid the prompt being overwritten
+bindkey -e  # moving the cursor relies on emacs bindings, even when $EDITOR is vi
+precmd_functions=(_completest_ready)  # avoid the prompt being overwritten; only report readiness
+_completest_ready() { printf '\\033]7777;completest-ready\\007'; }
+# Report when each completion is done, as it can take a while without printing anything
+autoload -U +X _main_complete
+functions[_completest_main_complete]=$functions[_main_complete]
+_main_complete() {
+    _completest_main_complete \"$@\"
+    local ret=$?
+    printf '\\033]7777;completest-done\\007'
+    return $ret
+}
+if [[ -n $COMPLETEST_MENU ]]; then
+    zmodload zsh/complist
+    zstyle ':completion:*' menu select
+    bindkey -M menuselect '^[[Z' reverse-menu-complete
+fi
 PS1='%% '
 PROMPT='%% '
 ";
@@ -87,13 +161,14 @@ PROMPT='%% '
     }
 
     /// Reuse an existing runtime's home
-    pub fn with_home(bin_root: PathBuf, home: PathBuf) -> std::io::Result<Self> {
+    pub fn with_home(bin_root: PathBuf, home: PathBuf) -> Result<Self, Error> {
         let path = build_path(bin_root);
 
         Ok(Self {
             path,
             home,
-            timeout: Duration::from_millis(100),
+            wait: WaitStrategy::marker(Duration::from_millis(50), Duration::from_millis(500)),
+            menu: false,
         })
     }
 
@@ -102,40 +177,375 @@ PROMPT='%% '
         &self.home
     }
 
+    /// Version of the shell being run, from `$ZSH_VERSION`
+    pub fn version(&self) -> Result<Version, Error> {
+        direct::version(zsh_version(&self.path), self.wait.get_timeout())
+    }
+
     /// Register a completion script
-    pub fn register(&mut self, name: &str, content: &str) -> std::io::Result<()> {
+    pub fn register(&mut self, name: &str, content: &str) -> Result<(), Error> {
         let path = self.home.join(format!("zsh/_{name}"));
         std::fs::create_dir_all(path.parent().expect("path created with parent"))?;
-        std::fs::write(path, content)
+        std::fs::write(path, content)?;
+        Ok(())
+    }
+
+    /// Whether `Tab` starts menu selection (`menu select`) rather than listing candidates
+    pub fn get_menu(&self) -> bool {
+        self.menu
+    }
+
+    /// Have `Tab` start menu selection (`menu select`), reporting the
+    /// [highlighted][Completion::get_highlighted] candidate
+    ///
+    /// Requires a home created by [`ZshRuntime::new`].
+    pub fn set_menu(&mut self, menu: bool) {
+        self.menu = menu;
+    }
+
+    /// Get the output from typing `input` into the shell
+    pub fn complete(&mut self, input: &str, term: &Term) -> Result<String, Error> {
+        self.complete_input(&Input::from(input), term)
+    }
+}
+
+impl PtyShell for ZshRuntime {
+    const ECHO: bool = false;
+
+    fn command(&self) -> Command {
+        let mut command = Command::new("zsh");
+        command.arg("--noglobalrcs");
+        command
+            .env("PATH", &self.path)
+            .env("TERM", "xterm")
+            .env("ZDOTDIR", &self.home);
+        if self.menu {
+            command.env("COMPLETEST_MENU", "1");
+        }
+        command
+    }
+
+    fn parse(&self, screen: &Screen, _: &Input) -> Completion {
+        parse::zsh(screen)
+    }
+}
+
+pty_runtime!(ZshRuntime);
+
+/// Abstract factory for [`ZshBashcompinitRuntime`]
+#[derive(Debug)]
+#[non_exhaustive]
+pub struct ZshBashcompinitRuntimeBuilder {}
+
+impl RuntimeBuilder for ZshBashcompinitRuntimeBuilder {
+    type Runtime = ZshBashcompinitRuntime;
+
+    fn name() -> &'static str {
+        "zsh-bashcompinit"
+    }
+
+    fn is_available() -> Result<(), Unavailable> {
+        find_binary("zsh")
+    }
+
+    fn new(bin_root: PathBuf, home: PathBuf) -> Result<Self::Runtime, Error> {
+        ZshBashcompinitRuntime::new(bin_root, home)
+    }
+
+    fn with_home(bin_root: PathBuf, home: PathBuf) -> Result<Self::Runtime, Error> {
+        ZshBashcompinitRuntime::with_home(bin_root, home)
+    }
+}
+
+/// Zsh runtime, loading bash completion scripts through `bashcompinit`
+#[derive(Debug)]
+#[cfg(unix)] // purely for rustdoc to pick it up
+pub struct ZshBashcompinitRuntime {
+    path: OsString,
+    home: PathBuf,
+    config: PathBuf,
+    wait: WaitStrategy,
+}
+
+impl ZshBashcompinitRuntime {
+    /// Initialize a new runtime's home
+    pub fn new(bin_root: PathBuf, home: PathBuf) -> Result<Self, Error> {
+        std::fs::create_dir_all(&home)?;
+
+        let config_path = home.join(".zshenv");
+        let config = "\
+autoload -U +X compinit && compinit -u # bypass compaudit security checking
+autoload -U +X bashcompinit && bashcompinit
+bindkey -e  # moving the cursor relies on emacs bindings, even when $EDITOR is vi
+precmd_functions=(_completest_ready)  # avoid the prompt being overwritten; only report readiness
+_completest_ready() { printf '\\033]7777;completest-ready\\007'; }
+# Report when each completion is done, as it can take a while without printing anything
+autoload -U +X _main_complete
+functions[_completest_main_complete]=$functions[_main_complete]
+_main_complete() {
+    _completest_main_complete \"$@\"
+    local ret=$?
+    printf '\\033]7777;completest-done\\007'
+    return $ret
+}
+PS1='%% '
+PROMPT='%% '
+";
+        std::fs::write(config_path, config)?;
+
+        let _ = std::fs::remove_file(home.join(".zcompdump"));
+
+        Self::with_home(bin_root, home)
+    }
+
+    /// Reuse an existing runtime's home
+    pub fn with_home(bin_root: PathBuf, home: PathBuf) -> Result<Self, Error> {
+        let config_path = home.join(".zshenv");
+        let path = build_path(bin_root);
+
+        Ok(Self {
+            path,
+            home,
+            config: config_path,
+            wait: WaitStrategy::marker(Duration::from_millis(50), Duration::from_millis(500)),
+        })
+    }
+
+    /// Location of the runtime's home directory
+    pub fn home(&self) -> &std::path::Path {
+        &self.home
+    }
+
+    /// Version of the shell being run, from `$ZSH_VERSION`
+    pub fn version(&self) -> Result<Version, Error> {
+        direct::version(zsh_version(&self.path), self.wait.get_timeout())
+    }
+
+    /// Register a completion script
+    ///
+    /// `content` is a bash completion script, sourced after `bashcompinit`.
+    pub fn register(&mut self, name: &str, content: &str) -> Result<(), Error> {
+        let path = self.home.join(format!("bash/{name}.bash"));
+        std::fs::create_dir_all(path.parent().expect("path created with parent"))?;
+        std::fs::write(path, content)?;
+
+        let mut file = std::fs::OpenOptions::new()
+            .append(true)
+            .open(&self.config)?;
+        writeln!(&mut file, "source \"$ZDOTDIR/bash/{name}.bash\"")?;
+        Ok(())
     }
 
     /// Get the output from typing `input` into the shell
-    pub fn complete(&mut self, input: &str, term: &Term) -> std::io::Result<String> {
+    pub fn complete(&mut self, input: &str, term: &Term) -> Result<String, Error> {
+        self.complete_input(&Input::from(input), term)
+    }
+}
+
+impl PtyShell for ZshBashcompinitRuntime {
+    const ECHO: bool = false;
+
+    fn command(&self) -> Command {
         let mut command = Command::new("zsh");
         command.arg("--noglobalrcs");
         command
             .env("PATH", &self.path)
             .env("TERM", "xterm")
             .env("ZDOTDIR", &self.home);
-        let echo = false;
-        comptest(command, echo, input, term, self.timeout)
+        command
+    }
+
+    fn parse(&self, screen: &Screen, _: &Input) -> Completion {
+        parse::zsh(screen)
+    }
+}
+
+pty_runtime!(ZshBashcompinitRuntime);
+
+/// Abstract factory for [`ZshZptyRuntime`]
+#[derive(Debug)]
+#[non_exhaustive]
+pub struct ZshZptyRuntimeBuilder {}
+
+impl RuntimeBuilder for ZshZptyRuntimeBuilder {
+    type Runtime = ZshZptyRuntime;
+
+    fn name() -> &'static str {
+        "zsh-zpty"
+    }
+
+    fn is_available() -> Result<(), Unavailable> {
+        find_binary("zsh")
+    }
+
+    fn new(bin_root: PathBuf, home: PathBuf) -> Result<Self::Runtime, Error> {
+        ZshZptyRuntime::new(bin_root, home)
     }
+
+    fn with_home(bin_root: PathBuf, home: PathBuf) -> Result<Self::Runtime, Error> {
+        ZshZptyRuntime::with_home(bin_root, home)
+    }
+}
+
+/// Zsh runtime, recording what completion functions pass to `compadd` rather than what is rendered
+///
+/// Like zsh's own test-suite, an interactive zsh is driven through the `zsh/zpty` module.  The home
+/// is laid out like [`ZshRuntime`]'s, so the two can share one.  Candidates are reported in the
+/// order they were added, with the display strings and groups given to `compadd`.
+#[derive(Debug)]
+#[cfg(unix)] // purely for rustdoc to pick it up
+pub struct ZshZptyRuntime {
+    path: OsString,
+    home: PathBuf,
+    timeout: Duration,
 }
 
-impl Runtime for ZshRuntime {
-    fn home(&self) -> &std::path::Path {
-        self.home()
+impl ZshZptyRuntime {
+    /// Initialize a new runtime's home
+    pub fn new(bin_root: PathBuf, home: PathBuf) -> Result<Self, Error> {
+        ZshRuntime::new(bin_root.clone(), home.clone())?;
+
+        Self::with_home(bin_root, home)
+    }
+
+    /// Reuse an existing runtime's home, like one from [`ZshRuntime::new`]
+    pub fn with_home(bin_root: PathBuf, home: PathBuf) -> Result<Self, Error> {
+        let path = build_path(bin_root);
+        std::fs::write(home.join(".completest-zpty.zsh"), ZSH_ZPTY_HOOK)?;
+
+        Ok(Self {
+            path,
+            home,
+            timeout: Duration::from_secs(10),
+        })
+    }
+
+    /// Location of the runtime's home directory
+    pub fn home(&self) -> &std::path::Path {
+        &self.home
+    }
+
+    /// Version of the shell being run, from `$ZSH_VERSION`
+    pub fn version(&self) -> Result<Version, Error> {
+        direct::version(zsh_version(&self.path), self.timeout)
+    }
+
+    /// Register a completion script
+    pub fn register(&mut self, name: &str, content: &str) -> Result<(), Error> {
+        let path = self.home.join(format!("zsh/_{name}"));
+        std::fs::create_dir_all(path.parent().expect("path created with parent"))?;
+        std::fs::write(path, content)?;
+        Ok(())
     }
 
-    fn register(&mut self, name: &str, content: &str) -> std::io::Result<()> {
-        self.register(name, content)
+    /// Get the candidates for `input`, one per line as zsh would display them
+    pub fn complete(&mut self, input: &str, term: &Term) -> Result<String, Error> {
+        self.complete_input(&Input::from(input), term)
     }
+}
 
-    fn complete(&mut self, input: &str, term: &Term) -> std::io::Result<String> {
-        self.complete(input, term)
+impl DirectShell for ZshZptyRuntime {
+    fn completion(&self, line: String, cursor: usize) -> Result<Completion, Error> {
+        // Type the line, then step back to the cursor with `backward-char`
+        let mut keys = line.clone();
+        keys.extend(std::iter::repeat_n('\x02', line[cursor..].chars().count()));
+        keys.push('\t');
+
+        let mut command = Command::new("zsh");
+        command
+            .env("PATH", &self.path)
+            .env("TERM", "xterm")
+            .env("ZDOTDIR", &self.home)
+            .args(["-f", "-c", ZSH_ZPTY_DRIVER, "completest"])
+            .arg(keys);
+        let output = direct::run(command, self.timeout)?;
+
+        let mut candidates = Vec::new();
+        for [value, group, display] in direct::records(&output) {
+            let mut candidate = direct::candidate(value, display);
+            if let Some((_, description)) = display.split_once(" -- ") {
+                candidate = candidate.description(description.trim());
+            }
+            if !group.is_empty() && group != "-default-" {
+                candidate = candidate.group(group);
+            }
+            candidates.push(candidate);
+        }
+        Ok(Completion::new(direct::screen(&candidates))
+            .buffer(line)
+            .cursor(cursor)
+            .candidates(candidates))
     }
 }
 
+direct_runtime!(ZshZptyRuntime);
+
+/// Sourced into the shell under `zpty` to record candidates to `$COMPLETEST_CANDIDATES`
+///
+/// Each candidate is recorded as its value, group, and display string, NUL-separated.
+const ZSH_ZPTY_HOOK: &str = r#"
+# Name groups after their tags and don't render candidates; they are recorded instead
+zstyle ':completion:*' group-name ''
+unsetopt AUTO_LIST AUTO_MENU LIST_AMBIGUOUS
+
+_completest_done() { print -rn -- $'\e]7777;completest-done\a' }
+comppostfuncs=(_completest_done)
+
+compadd() {
+    local completest_i completest_opt completest_group completest_display
+    for ((completest_i = 1; completest_i <= $#; completest_i++)); do
+        completest_opt=${@[completest_i]}
+        case $completest_opt in
+            # Matches collected into an array rather than offered
+            -[OAD]*) builtin compadd "$@"; return ;;
+            -[JV]) completest_group=${@[++completest_i]} ;;
+            -[JV]?*) completest_group=${completest_opt#-?} ;;
+            -d) completest_display=${@[++completest_i]} ;;
+            -*[PSpsiIWXxrREFM]) (( completest_i++ )) ;;
+            --) break ;;
+            -*) ;;
+            *) break ;;
+        esac
+    done
+
+    local -a completest_matches completest_displays
+    if [[ $completest_display == \(* ]]; then
+        eval "completest_displays=$completest_display"
+    elif [[ -n $completest_display ]]; then
+        completest_displays=("${(@P)completest_display}")
+    fi
+    builtin compadd -O completest_matches -D completest_displays "$@"
+    for ((completest_i = 1; completest_i <= $#completest_matches; completest_i++)); do
+        printf '%s\0%s\0%s\0' "$completest_matches[completest_i]" "$completest_group" \
+            "$completest_displays[completest_i]" >> $COMPLETEST_CANDIDATES
+    done
+
+    builtin compadd "$@"
+}
+"#;
+
+/// Type `$1` into an interactive zsh under `zpty` and print the recorded candidates
+const ZSH_ZPTY_DRIVER: &str = r#"
+zmodload zsh/zpty || exit
+export COMPLETEST_CANDIDATES=$ZDOTDIR/.completest-candidates-$$
+: >| $COMPLETEST_CANDIDATES
+
+zpty completest zsh -i || exit
+# Expand the markers so the echoed command doesn't match
+zpty -w completest 'source $ZDOTDIR/.completest-zpty.zsh && print completest-${:-loaded} || print completest-${:-failed}'
+zpty -r completest completest_screen '*completest-(loaded|failed)*' || exit
+if [[ $completest_screen != *completest-loaded* ]]; then
+    print -u2 -r -- $completest_screen
+    exit 1
+fi
+zpty -w -n completest "$1"
+zpty -r completest completest_screen '*completest-done*' || exit
+zpty -d completest
+
+cat -- $COMPLETEST_CANDIDATES
+rm -f -- $COMPLETEST_CANDIDATES
+"#;
+
 /// Abstract factory for [`BashRuntime`]
 #[derive(Debug)]
 #[non_exhaustive]
@@ -148,11 +558,16 @@ impl RuntimeBuilder for BashRuntimeBuilder {
         "bash"
     }
 
-    fn new(bin_root: PathBuf, home: PathBuf) -> std::io::Result<Self::Runtime> {
+    fn is_available() -> Result<(), Unavailable> {
+        find_binary("bash")?;
+        find_file("/etc/bash_completion", "bash-completion")
+    }
+
+    fn new(bin_root: PathBuf, home: PathBuf) -> Result<Self::Runtime, Error> {
         BashRuntime::new(bin_root, home)
     }
 
-    fn with_home(bin_root: PathBuf, home: PathBuf) -> std::io::Result<Self::Runtime> {
+    fn with_home(bin_root: PathBuf, home: PathBuf) -> Result<Self::Runtime, Error> {
         BashRuntime::with_home(bin_root, home)
     }
 }
@@ -164,18 +579,24 @@ pub struct BashRuntime {
     path: OsString,
     home: PathBuf,
     config: PathBuf,
-    timeout: Duration,
+    wait: WaitStrategy,
+    menu: bool,
 }
 
 impl BashRuntime {
     /// Initialize a new runtime's home
-    pub fn new(bin_root: PathBuf, home: PathBuf) -> std::io::Result<Self> {
+    pub fn new(bin_root: PathBuf, home: PathBuf) -> Result<Self, Error> {
         std::fs::create_dir_all(&home)?;
 
         let config_path = home.join(".bashrc");
         let inputrc_path = home.join(".inputrc");
         let config = "\
 PS1='% '
+PROMPT_COMMAND='printf \"\\033]7777;completest-ready\\007\"'
+if [[ -n $COMPLETEST_MENU ]]; then
+    bind 'TAB: menu-complete'
+    bind '\"\\e[Z\": menu-complete-backward'
+fi
 . /etc/bash_completion
 "
         .to_owned();
@@ -190,7 +611,7 @@ PS1='% '
     }
 
     /// Reuse an existing runtime's home
-    pub fn with_home(bin_root: PathBuf, home: PathBuf) -> std::io::Result<Self> {
+    pub fn with_home(bin_root: PathBuf, home: PathBuf) -> Result<Self, Error> {
         let config_path = home.join(".bashrc");
         let path = build_path(bin_root);
 
@@ -198,7 +619,8 @@ PS1='% '
             path,
             home,
             config: config_path,
-            timeout: Duration::from_millis(50),
+            wait: WaitStrategy::quiet(Duration::from_millis(50)),
+            menu: false,
         })
     }
 
@@ -207,8 +629,13 @@ PS1='% '
         &self.home
     }
 
+    /// Version of the shell being run, from `$BASH_VERSION`
+    pub fn version(&self) -> Result<Version, Error> {
+        direct::version(bash_version(&self.path), self.wait.get_timeout())
+    }
+
     /// Register a completion script
-    pub fn register(&mut self, _name: &str, content: &str) -> std::io::Result<()> {
+    pub fn register(&mut self, _name: &str, content: &str) -> Result<(), Error> {
         let mut file = std::fs::OpenOptions::new()
             .append(true)
             .open(&self.config)?;
@@ -216,8 +643,29 @@ PS1='% '
         Ok(())
     }
 
+    /// Whether `Tab` cycles through candidates (`menu-complete`) rather than listing them
+    pub fn get_menu(&self) -> bool {
+        self.menu
+    }
+
+    /// Have `Tab` cycle through candidates (`menu-complete`), reporting the inserted one as
+    /// [highlighted][Completion::get_highlighted]
+    ///
+    /// Requires a home created by [`BashRuntime::new`].
+    pub fn set_menu(&mut self, menu: bool) {
+        self.menu = menu;
+    }
+
     /// Get the output from typing `input` into the shell
-    pub fn complete(&mut self, input: &str, term: &Term) -> std::io::Result<String> {
+    pub fn complete(&mut self, input: &str, term: &Term) -> Result<String, Error> {
+        self.complete_input(&Input::from(input), term)
+    }
+}
+
+impl PtyShell for BashRuntime {
+    const ECHO: bool = true;
+
+    fn command(&self) -> Command {
         let mut command = Command::new("bash");
         let inputrc_path = self.home.join(".inputrc");
         command
@@ -229,25 +677,26 @@ PS1='% '
                 OsStr::new("--rcfile"),
                 self.config.as_os_str(),
             ]);
-        let echo = !input.contains("\t\t");
-        comptest(command, echo, input, term, self.timeout)
-    }
-}
-
-impl Runtime for BashRuntime {
-    fn home(&self) -> &std::path::Path {
-        self.home()
+        if self.menu {
+            command.env("COMPLETEST_MENU", "1");
+        }
+        command
     }
 
-    fn register(&mut self, name: &str, content: &str) -> std::io::Result<()> {
-        self.register(name, content)
+    fn complete_echo(&self, input: &Input) -> bool {
+        // Only list the candidates, unless `menu-complete` is cycling through them on the
+        // command-line
+        self.menu || !double_tab(input)
     }
 
-    fn complete(&mut self, input: &str, term: &Term) -> std::io::Result<String> {
-        self.complete(input, term)
+    fn parse(&self, screen: &Screen, input: &Input) -> Completion {
+        let typed = typed(input);
+        parse::bash(screen, self.menu.then_some(typed.as_str()))
     }
 }
 
+pty_runtime!(BashRuntime);
+
 /// Abstract factory for [`FishRuntime`]
 #[derive(Debug)]
 #[non_exhaustive]
@@ -260,11 +709,15 @@ impl RuntimeBuilder for FishRuntimeBuilder {
         "fish"
     }
 
-    fn new(bin_root: PathBuf, home: PathBuf) -> std::io::Result<Self::Runtime> {
+    fn is_available() -> Result<(), Unavailable> {
+        find_binary("fish")
+    }
+
+    fn new(bin_root: PathBuf, home: PathBuf) -> Result<Self::Runtime, Error> {
         FishRuntime::new(bin_root, home)
     }
 
-    fn with_home(bin_root: PathBuf, home: PathBuf) -> std::io::Result<Self::Runtime> {
+    fn with_home(bin_root: PathBuf, home: PathBuf) -> Result<Self::Runtime, Error> {
         FishRuntime::with_home(bin_root, home)
     }
 }
@@ -275,12 +728,12 @@ impl RuntimeBuilder for FishRuntimeBuilder {
 pub struct FishRuntime {
     path: OsString,
     home: PathBuf,
-    timeout: Duration,
+    wait: WaitStrategy,
 }
 
 impl FishRuntime {
     /// Initialize a new runtime's home
-    pub fn new(bin_root: PathBuf, home: PathBuf) -> std::io::Result<Self> {
+    pub fn new(bin_root: PathBuf, home: PathBuf) -> Result<Self, Error> {
         std::fs::create_dir_all(&home)?;
 
         let config_path = home.join("fish/config.fish");
@@ -292,6 +745,9 @@ end
 function fish_prompt
     printf '%% '
 end;
+function __completest_ready --on-event fish_prompt
+    printf '\\033]7777;completest-ready\\007'
+end;
 "
         .to_owned();
         std::fs::create_dir_all(config_path.parent().expect("path created with parent"))?;
@@ -301,13 +757,13 @@ end;
     }
 
     /// Reuse an existing runtime's home
-    pub fn with_home(bin_root: PathBuf, home: PathBuf) -> std::io::Result<Self> {
+    pub fn with_home(bin_root: PathBuf, home: PathBuf) -> Result<Self, Error> {
         let path = build_path(bin_root);
 
         Ok(Self {
             path,
             home,
-            timeout: Duration::from_millis(50),
+            wait: WaitStrategy::quiet(Duration::from_millis(50)),
         })
     }
 
@@ -316,40 +772,155 @@ end;
         &self.home
     }
 
+    /// Version of the shell being run, from `$FISH_VERSION`
+    pub fn version(&self) -> Result<Version, Error> {
+        direct::version(
+            fish_version(&self.path, &self.home),
+            self.wait.get_timeout(),
+        )
+    }
+
     /// Register a completion script
-    pub fn register(&mut self, name: &str, content: &str) -> std::io::Result<()> {
+    pub fn register(&mut self, name: &str, content: &str) -> Result<(), Error> {
         let path = self.home.join(format!("fish/completions/{name}.fish"));
         std::fs::create_dir_all(path.parent().expect("path created with parent"))?;
-        std::fs::write(path, content)
+        std::fs::write(path, content)?;
+        Ok(())
     }
 
     /// Get the output from typing `input` into the shell
-    pub fn complete(&mut self, input: &str, term: &Term) -> std::io::Result<String> {
+    pub fn complete(&mut self, input: &str, term: &Term) -> Result<String, Error> {
+        self.complete_input(&Input::from(input), term)
+    }
+}
+
+impl PtyShell for FishRuntime {
+    const ECHO: bool = false;
+
+    fn command(&self) -> Command {
         let mut command = Command::new("fish");
         command
             .env("PATH", &self.path)
             // fish requires TERM to be set.
             .env("TERM", "xterm")
             .env("XDG_CONFIG_HOME", &self.home);
-        let echo = false;
-        comptest(command, echo, input, term, self.timeout)
+        command
+    }
+
+    fn parse(&self, screen: &Screen, _: &Input) -> Completion {
+        parse::fish(screen)
+    }
+}
+
+pty_runtime!(FishRuntime);
+
+/// Abstract factory for [`FishDirectRuntime`]
+#[derive(Debug)]
+#[non_exhaustive]
+pub struct FishDirectRuntimeBuilder {}
+
+impl RuntimeBuilder for FishDirectRuntimeBuilder {
+    type Runtime = FishDirectRuntime;
+
+    fn name() -> &'static str {
+        "fish-direct"
+    }
+
+    fn is_available() -> Result<(), Unavailable> {
+        find_binary("fish")
     }
+
+    fn new(bin_root: PathBuf, home: PathBuf) -> Result<Self::Runtime, Error> {
+        FishDirectRuntime::new(bin_root, home)
+    }
+
+    fn with_home(bin_root: PathBuf, home: PathBuf) -> Result<Self::Runtime, Error> {
+        FishDirectRuntime::with_home(bin_root, home)
+    }
+}
+
+/// Fish runtime, asking for completions with `complete --do-complete` rather than through a
+/// terminal
+///
+/// The home is laid out like [`FishRuntime`]'s, so the two can share one.  Candidates are reported
+/// in the order fish prints them, completing the command-line up to the cursor.
+#[derive(Debug)]
+#[cfg(unix)] // purely for rustdoc to pick it up
+pub struct FishDirectRuntime {
+    path: OsString,
+    home: PathBuf,
+    timeout: Duration,
 }
 
-impl Runtime for FishRuntime {
-    fn home(&self) -> &std::path::Path {
-        self.home()
+impl FishDirectRuntime {
+    /// Initialize a new runtime's home
+    pub fn new(bin_root: PathBuf, home: PathBuf) -> Result<Self, Error> {
+        FishRuntime::new(bin_root.clone(), home.clone())?;
+
+        Self::with_home(bin_root, home)
+    }
+
+    /// Reuse an existing runtime's home
+    pub fn with_home(bin_root: PathBuf, home: PathBuf) -> Result<Self, Error> {
+        let path = build_path(bin_root);
+
+        Ok(Self {
+            path,
+            home,
+            timeout: Duration::from_secs(10),
+        })
+    }
+
+    /// Location of the runtime's home directory
+    pub fn home(&self) -> &std::path::Path {
+        &self.home
+    }
+
+    /// Version of the shell being run, from `$FISH_VERSION`
+    pub fn version(&self) -> Result<Version, Error> {
+        direct::version(fish_version(&self.path, &self.home), self.timeout)
+    }
+
+    /// Register a completion script
+    pub fn register(&mut self, name: &str, content: &str) -> Result<(), Error> {
+        let path = self.home.join(format!("fish/completions/{name}.fish"));
+        std::fs::create_dir_all(path.parent().expect("path created with parent"))?;
+        std::fs::write(path, content)?;
+        Ok(())
     }
 
-    fn register(&mut self, name: &str, content: &str) -> std::io::Result<()> {
-        self.register(name, content)
+    /// Get fish's completions for `input`, one `value\tdescription` per line
+    pub fn complete(&mut self, input: &str, term: &Term) -> Result<String, Error> {
+        self.complete_input(&Input::from(input), term)
     }
+}
 
-    fn complete(&mut self, input: &str, term: &Term) -> std::io::Result<String> {
-        self.complete(input, term)
+impl DirectShell for FishDirectRuntime {
+    fn completion(&self, line: String, cursor: usize) -> Result<Completion, Error> {
+        let mut command = Command::new("fish");
+        command
+            .env("PATH", &self.path)
+            .env("XDG_CONFIG_HOME", &self.home)
+            .args(["--command", "complete --do-complete=$argv[1]", "--"])
+            .arg(&line[..cursor]);
+        let output = direct::run(command, self.timeout)?;
+
+        let candidates: Vec<_> = output
+            .lines()
+            .map(|line| match line.split_once('\t') {
+                Some((value, description)) => Candidate::new(value).description(description),
+                None => Candidate::new(line),
+            })
+            .collect();
+        Ok(Completion::new(output)
+            .buffer(line)
+            .cursor(cursor)
+            .candidates(candidates))
     }
 }
 
+direct_runtime!(FishDirectRuntime);
+
 /// Abstract factory for [`ElvishRuntime`]
 #[derive(Debug)]
 #[non_exhaustive]
@@ -362,11 +933,15 @@ impl RuntimeBuilder for ElvishRuntimeBuilder {
         "elvish"
     }
 
-    fn new(bin_root: PathBuf, home: PathBuf) -> std::io::Result<Self::Runtime> {
+    fn is_available() -> Result<(), Unavailable> {
+        find_binary("elvish")
+    }
+
+    fn new(bin_root: PathBuf, home: PathBuf) -> Result<Self::Runtime, Error> {
         ElvishRuntime::new(bin_root, home)
     }
 
-    fn with_home(bin_root: PathBuf, home: PathBuf) -> std::io::Result<Self::Runtime> {
+    fn with_home(bin_root: PathBuf, home: PathBuf) -> Result<Self::Runtime, Error> {
         ElvishRuntime::with_home(bin_root, home)
     }
 }
@@ -378,18 +953,19 @@ pub struct ElvishRuntime {
     path: OsString,
     home: PathBuf,
     config: PathBuf,
-    timeout: Duration,
+    wait: WaitStrategy,
 }
 
 impl ElvishRuntime {
     /// Initialize a new runtime's home
-    pub fn new(bin_root: PathBuf, home: PathBuf) -> std::io::Result<Self> {
+    pub fn new(bin_root: PathBuf, home: PathBuf) -> Result<Self, Error> {
         std::fs::create_dir_all(&home)?;
 
         let config_path = home.join("elvish/rc.elv");
         let config = "\
 set edit:rprompt = (constantly \"\")
 set edit:prompt = (constantly \"% \")
+set edit:before-readline = [ { print \"\\x1b]7777;completest-ready\\x07\" } ]
 "
         .to_owned();
         std::fs::create_dir_all(config_path.parent().expect("path created with parent"))?;
@@ -399,7 +975,7 @@ set edit:prompt = (constantly \"% \")
     }
 
     /// Reuse an existing runtime's home
-    pub fn with_home(bin_root: PathBuf, home: PathBuf) -> std::io::Result<Self> {
+    pub fn with_home(bin_root: PathBuf, home: PathBuf) -> Result<Self, Error> {
         let config_path = home.join("elvish/rc.elv");
         let path = build_path(bin_root);
 
@@ -407,7 +983,7 @@ set edit:prompt = (constantly \"% \")
             path,
             home,
             config: config_path,
-            timeout: Duration::from_millis(50),
+            wait: WaitStrategy::quiet(Duration::from_millis(50)),
         })
     }
 
@@ -416,8 +992,13 @@ set edit:prompt = (constantly \"% \")
         &self.home
     }
 
+    /// Version of the shell being run, from `elvish -version`
+    pub fn version(&self) -> Result<Version, Error> {
+        direct::version(elvish_version(&self.path), self.wait.get_timeout())
+    }
+
     /// Register a completion script
-    pub fn register(&mut self, _name: &str, content: &str) -> std::io::Result<()> {
+    pub fn register(&mut self, _name: &str, content: &str) -> Result<(), Error> {
         let mut file = std::fs::OpenOptions::new()
             .append(true)
             .open(&self.config)?;
@@ -426,86 +1007,1180 @@ set edit:prompt = (constantly \"% \")
     }
 
     /// Get the output from typing `input` into the shell
-    pub fn complete(&mut self, input: &str, term: &Term) -> std::io::Result<String> {
-        let mut command = Command::new("elvish");
-        command
+    pub fn complete(&mut self, input: &str, term: &Term) -> Result<String, Error> {
+        self.complete_input(&Input::from(input), term)
+    }
+}
+
+impl PtyShell for ElvishRuntime {
+    const ECHO: bool = false;
+
+    fn command(&self) -> Command {
+        let mut command = Command::new("elvish");
+        command
+            .env("PATH", &self.path)
+            .env("TERM", "xterm")
+            .env("XDG_CONFIG_HOME", &self.home);
+        command
+    }
+
+    fn parse(&self, screen: &Screen, _: &Input) -> Completion {
+        parse::elvish(screen)
+    }
+}
+
+pty_runtime!(ElvishRuntime);
+
+/// Abstract factory for [`ElvishDirectRuntime`]
+#[derive(Debug)]
+#[non_exhaustive]
+pub struct ElvishDirectRuntimeBuilder {}
+
+impl RuntimeBuilder for ElvishDirectRuntimeBuilder {
+    type Runtime = ElvishDirectRuntime;
+
+    fn name() -> &'static str {
+        "elvish-direct"
+    }
+
+    fn is_available() -> Result<(), Unavailable> {
+        find_binary("elvish")
+    }
+
+    fn new(bin_root: PathBuf, home: PathBuf) -> Result<Self::Runtime, Error> {
+        ElvishDirectRuntime::new(bin_root, home)
+    }
+
+    fn with_home(bin_root: PathBuf, home: PathBuf) -> Result<Self::Runtime, Error> {
+        ElvishDirectRuntime::with_home(bin_root, home)
+    }
+}
+
+/// Elvish runtime, calling `$edit:completion:arg-completer` directly rather than through a terminal
+///
+/// `rc.elv` is run with `elvish -norc`, with enough of the `edit:` module stubbed out for
+/// completion scripts to register themselves.  The home is laid out like [`ElvishRuntime`]'s, so
+/// the two can share one.  Candidates are reported in the order the completer outputs them, keeping
+/// those that start with the word being completed.
+#[derive(Debug)]
+#[cfg(unix)] // purely for rustdoc to pick it up
+pub struct ElvishDirectRuntime {
+    path: OsString,
+    home: PathBuf,
+    config: PathBuf,
+    timeout: Duration,
+}
+
+impl ElvishDirectRuntime {
+    /// Initialize a new runtime's home
+    pub fn new(bin_root: PathBuf, home: PathBuf) -> Result<Self, Error> {
+        ElvishRuntime::new(bin_root.clone(), home.clone())?;
+
+        Self::with_home(bin_root, home)
+    }
+
+    /// Reuse an existing runtime's home
+    pub fn with_home(bin_root: PathBuf, home: PathBuf) -> Result<Self, Error> {
+        let config_path = home.join("elvish/rc.elv");
+        let path = build_path(bin_root);
+
+        Ok(Self {
+            path,
+            home,
+            config: config_path,
+            timeout: Duration::from_secs(10),
+        })
+    }
+
+    /// Location of the runtime's home directory
+    pub fn home(&self) -> &std::path::Path {
+        &self.home
+    }
+
+    /// Version of the shell being run, from `elvish -version`
+    pub fn version(&self) -> Result<Version, Error> {
+        direct::version(elvish_version(&self.path), self.timeout)
+    }
+
+    /// Register a completion script
+    pub fn register(&mut self, _name: &str, content: &str) -> Result<(), Error> {
+        let mut file = std::fs::OpenOptions::new()
+            .append(true)
+            .open(&self.config)?;
+        writeln!(&mut file, "{content}")?;
+        Ok(())
+    }
+
+    /// Get the candidates for `input`, one per line as elvish would display them
+    pub fn complete(&mut self, input: &str, term: &Term) -> Result<String, Error> {
+        self.complete_input(&Input::from(input), term)
+    }
+}
+
+impl DirectShell for ElvishDirectRuntime {
+    fn completion(&self, line: String, cursor: usize) -> Result<Completion, Error> {
+        // The completer is given the words up to the cursor, including the one being completed
+        let mut words: Vec<_> = line[..cursor].split_whitespace().collect();
+        if line[..cursor].ends_with(char::is_whitespace) || words.is_empty() {
+            words.push("");
+        }
+
+        let rc = std::fs::read_to_string(&self.config)?;
+        let code = format!("{ELVISH_EDIT_STUB}\n{rc}\n{ELVISH_DIRECT_DRIVER}");
+        let mut command = Command::new("elvish");
+        command
+            .env("PATH", &self.path)
+            .env("XDG_CONFIG_HOME", &self.home)
+            .args(["-norc", "-c", &code])
+            .args(&words);
+        let output = direct::run(command, self.timeout)?;
+
+        let candidates: Vec<_> = direct::records(&output)
+            .into_iter()
+            .map(|[value, display]| direct::candidate(value, display))
+            .collect();
+        Ok(Completion::new(direct::screen(&candidates))
+            .buffer(line)
+            .cursor(cursor)
+            .candidates(candidates))
+    }
+}
+
+direct_runtime!(ElvishDirectRuntime);
+
+/// Stands in for the `edit:` module, which only exists in interactive elvish
+const ELVISH_EDIT_STUB: &str = r#"
+fn completest-complex-candidate {|stem &display='' &code-suffix=' ' &display-suffix=''|
+    put [&stem=$stem &display=$display &code-suffix=$code-suffix]
+}
+var edit: = (ns [
+    &completion:=(ns [&arg-completer=[&]])
+    &complex-candidate~=$completest-complex-candidate~
+    &prompt=$nil
+    &rprompt=$nil
+    &before-readline=[]
+])
+"#;
+
+/// Print each candidate's value and display string, NUL-separated
+const ELVISH_DIRECT_DRIVER: &str = r#"
+use str
+var completest-completer = $edit:completion:arg-completer[$args[0]]
+var completest-current = $args[-1]
+$completest-completer $@args | each {|candidate|
+    var stem display = $candidate ''
+    if (eq (kind-of $candidate) map) {
+        set stem display = $candidate[stem] (to-string $candidate[display])
+    }
+    if (str:has-prefix $stem $completest-current) {
+        print $stem"\x00"$display"\x00"
+    }
+}
+"#;
+
+/// Abstract factory for [`PwshRuntime`]
+#[derive(Debug)]
+#[non_exhaustive]
+pub struct PwshRuntimeBuilder {}
+
+impl RuntimeBuilder for PwshRuntimeBuilder {
+    type Runtime = PwshRuntime;
+
+    fn name() -> &'static str {
+        "pwsh"
+    }
+
+    fn is_available() -> Result<(), Unavailable> {
+        find_binary("pwsh")
+    }
+
+    fn new(bin_root: PathBuf, home: PathBuf) -> Result<Self::Runtime, Error> {
+        PwshRuntime::new(bin_root, home)
+    }
+
+    fn with_home(bin_root: PathBuf, home: PathBuf) -> Result<Self::Runtime, Error> {
+        PwshRuntime::with_home(bin_root, home)
+    }
+}
+
+/// PowerShell runtime
+#[derive(Debug)]
+#[cfg(unix)] // purely for rustdoc to pick it up
+pub struct PwshRuntime {
+    path: OsString,
+    home: PathBuf,
+    config: PathBuf,
+    wait: WaitStrategy,
+}
+
+impl PwshRuntime {
+    /// Initialize a new runtime's home
+    pub fn new(bin_root: PathBuf, home: PathBuf) -> Result<Self, Error> {
+        std::fs::create_dir_all(&home)?;
+
+        let config_path = home.join("powershell/Microsoft.PowerShell_profile.ps1");
+        let config = "\
+function prompt {
+    [Console]::Write(\"`e]7777;completest-ready`a\")
+    '% '
+}
+Set-PSReadLineOption -EditMode Emacs -BellStyle None -HistorySaveStyle SaveNothing
+Set-PSReadLineOption -PredictionSource None -ShowToolTips:$false
+Set-PSReadLineOption -Colors @{ Selection = \"`e[7m\" }
+Set-PSReadLineKeyHandler -Chord Tab -Function MenuComplete
+# Report when each completion is done, as it can take a while without printing anything
+$function:__completest_TabExpansion2 = $function:TabExpansion2
+function TabExpansion2 {
+    __completest_TabExpansion2 @args
+    [Console]::Write(\"`e]7777;completest-done`a\")
+}
+"
+        .to_owned();
+        std::fs::create_dir_all(config_path.parent().expect("path created with parent"))?;
+        std::fs::write(config_path, config)?;
+
+        Self::with_home(bin_root, home)
+    }
+
+    /// Reuse an existing runtime's home
+    pub fn with_home(bin_root: PathBuf, home: PathBuf) -> Result<Self, Error> {
+        let config_path = home.join("powershell/Microsoft.PowerShell_profile.ps1");
+        let path = build_path(bin_root);
+
+        Ok(Self {
+            path,
+            home,
+            config: config_path,
+            wait: WaitStrategy::marker(Duration::from_millis(50), Duration::from_millis(500)),
+        })
+    }
+
+    /// Location of the runtime's home directory
+    pub fn home(&self) -> &std::path::Path {
+        &self.home
+    }
+
+    /// Version of the shell being run, from `$PSVersionTable`
+    pub fn version(&self) -> Result<Version, Error> {
+        let mut command = Command::new("pwsh");
+        command.env("PATH", &self.path).args([
+            "-NoLogo",
+            "-NoProfile",
+            "-Command",
+            "$PSVersionTable.PSVersion.ToString()",
+        ]);
+        direct::version(command, self.wait.get_timeout())
+    }
+
+    /// Register a completion script
+    pub fn register(&mut self, _name: &str, content: &str) -> Result<(), Error> {
+        let mut file = std::fs::OpenOptions::new()
+            .append(true)
+            .open(&self.config)?;
+        writeln!(&mut file, "{content}")?;
+        Ok(())
+    }
+
+    /// Get the output from typing `input` into the shell
+    pub fn complete(&mut self, input: &str, term: &Term) -> Result<String, Error> {
+        self.complete_input(&Input::from(input), term)
+    }
+}
+
+impl PtyShell for PwshRuntime {
+    const ECHO: bool = false;
+
+    fn command(&self) -> Command {
+        // Dot-source the profile so its `prompt` is global, skipping the user's own profile
+        let profile = self.config.to_string_lossy().replace('\'', "''");
+        let mut command = Command::new("pwsh");
+        command
+            .env("PATH", &self.path)
+            .env("TERM", "xterm")
+            .env("XDG_CONFIG_HOME", &self.home)
+            .env("POWERSHELL_TELEMETRY_OPTOUT", "1")
+            .env("POWERSHELL_UPDATECHECK", "Off")
+            .args(["-NoLogo", "-NoProfile", "-NoExit", "-Command"])
+            .arg(format!(". '{profile}'"));
+        command
+    }
+
+    fn parse(&self, screen: &Screen, _: &Input) -> Completion {
+        parse::pwsh(screen)
+    }
+}
+
+pty_runtime!(PwshRuntime);
+
+/// Abstract factory for [`XonshRuntime`]
+#[derive(Debug)]
+#[non_exhaustive]
+pub struct XonshRuntimeBuilder {}
+
+impl RuntimeBuilder for XonshRuntimeBuilder {
+    type Runtime = XonshRuntime;
+
+    fn name() -> &'static str {
+        "xonsh"
+    }
+
+    fn is_available() -> Result<(), Unavailable> {
+        find_binary("xonsh")
+    }
+
+    fn new(bin_root: PathBuf, home: PathBuf) -> Result<Self::Runtime, Error> {
+        XonshRuntime::new(bin_root, home)
+    }
+
+    fn with_home(bin_root: PathBuf, home: PathBuf) -> Result<Self::Runtime, Error> {
+        XonshRuntime::with_home(bin_root, home)
+    }
+}
+
+/// Xonsh runtime
+#[derive(Debug)]
+#[cfg(unix)] // purely for rustdoc to pick it up
+pub struct XonshRuntime {
+    path: OsString,
+    home: PathBuf,
+    wait: WaitStrategy,
+}
+
+impl XonshRuntime {
+    /// Initialize a new runtime's home
+    pub fn new(bin_root: PathBuf, home: PathBuf) -> Result<Self, Error> {
+        std::fs::create_dir_all(&home)?;
+
+        let config_path = home.join("xonsh/rc.xsh");
+        let config = "\
+$PROMPT = '% '
+$RIGHT_PROMPT = ''
+$BOTTOM_TOOLBAR = ''
+$TITLE = ''
+$AUTO_SUGGEST = False
+$XONSH_HISTORY_BACKEND = 'dummy'
+$COMPLETIONS_DISPLAY = 'multi'
+@events.on_pre_prompt
+def _completest_ready():
+    print('\\x1b]7777;completest-ready\\x07', end='', flush=True)
+"
+        .to_owned();
+        std::fs::create_dir_all(config_path.parent().expect("path created with parent"))?;
+        std::fs::write(config_path, config)?;
+
+        Self::with_home(bin_root, home)
+    }
+
+    /// Reuse an existing runtime's home
+    pub fn with_home(bin_root: PathBuf, home: PathBuf) -> Result<Self, Error> {
+        let path = build_path(bin_root);
+
+        Ok(Self {
+            path,
+            home,
+            wait: WaitStrategy::quiet(Duration::from_millis(100)),
+        })
+    }
+
+    /// Location of the runtime's home directory
+    pub fn home(&self) -> &std::path::Path {
+        &self.home
+    }
+
+    /// Version of the shell being run, from `$XONSH_VERSION`
+    pub fn version(&self) -> Result<Version, Error> {
+        let mut command = Command::new("xonsh");
+        command
+            .env("PATH", &self.path)
+            .args(["--no-rc", "-c", "print($XONSH_VERSION)"]);
+        direct::version(command, self.wait.get_timeout())
+    }
+
+    /// Register a completion script
+    pub fn register(&mut self, name: &str, content: &str) -> Result<(), Error> {
+        let path = self.home.join(format!("xonsh/rc.d/{name}.xsh"));
+        std::fs::create_dir_all(path.parent().expect("path created with parent"))?;
+        std::fs::write(path, content)?;
+        Ok(())
+    }
+
+    /// Get the output from typing `input` into the shell
+    pub fn complete(&mut self, input: &str, term: &Term) -> Result<String, Error> {
+        self.complete_input(&Input::from(input), term)
+    }
+}
+
+impl PtyShell for XonshRuntime {
+    const ECHO: bool = false;
+
+    fn command(&self) -> Command {
+        let mut command = Command::new("xonsh");
+        command
+            .arg("--interactive")
+            .env("PATH", &self.path)
+            .env("TERM", "xterm")
+            .env("XDG_CONFIG_HOME", &self.home)
+            .env("XDG_DATA_HOME", &self.home)
+            // Skip the system-wide `xonshrc`
+            .env("XONSHRC", self.home.join("xonsh/rc.xsh"))
+            .env("XONSHRC_DIR", self.home.join("xonsh/rc.d"));
+        command
+    }
+
+    fn parse(&self, screen: &Screen, _: &Input) -> Completion {
+        parse::xonsh(screen)
+    }
+}
+
+pty_runtime!(XonshRuntime);
+
+/// Abstract factory for [`TcshRuntime`]
+#[derive(Debug)]
+#[non_exhaustive]
+pub struct TcshRuntimeBuilder {}
+
+impl RuntimeBuilder for TcshRuntimeBuilder {
+    type Runtime = TcshRuntime;
+
+    fn name() -> &'static str {
+        "tcsh"
+    }
+
+    fn is_available() -> Result<(), Unavailable> {
+        find_binary("tcsh")
+    }
+
+    fn new(bin_root: PathBuf, home: PathBuf) -> Result<Self::Runtime, Error> {
+        TcshRuntime::new(bin_root, home)
+    }
+
+    fn with_home(bin_root: PathBuf, home: PathBuf) -> Result<Self::Runtime, Error> {
+        TcshRuntime::with_home(bin_root, home)
+    }
+}
+
+/// tcsh runtime
+#[derive(Debug)]
+#[cfg(unix)] // purely for rustdoc to pick it up
+pub struct TcshRuntime {
+    path: OsString,
+    home: PathBuf,
+    config: PathBuf,
+    wait: WaitStrategy,
+}
+
+impl TcshRuntime {
+    /// Initialize a new runtime's home
+    pub fn new(bin_root: PathBuf, home: PathBuf) -> Result<Self, Error> {
+        std::fs::create_dir_all(&home)?;
+
+        let config_path = home.join(".tcshrc");
+        let config = "\
+set prompt = '%% '
+set autolist
+set nobeep
+unset savehist
+alias precmd 'printf \"\\033]7777;completest-ready\\007\"'
+"
+        .to_owned();
+        std::fs::write(config_path, config)?;
+
+        Self::with_home(bin_root, home)
+    }
+
+    /// Reuse an existing runtime's home
+    pub fn with_home(bin_root: PathBuf, home: PathBuf) -> Result<Self, Error> {
+        let config_path = home.join(".tcshrc");
+        let path = build_path(bin_root);
+
+        Ok(Self {
+            path,
+            home,
+            config: config_path,
+            wait: WaitStrategy::quiet(Duration::from_millis(50)),
+        })
+    }
+
+    /// Location of the runtime's home directory
+    pub fn home(&self) -> &std::path::Path {
+        &self.home
+    }
+
+    /// Version of the shell being run, from `$tcsh`
+    pub fn version(&self) -> Result<Version, Error> {
+        let mut command = Command::new("tcsh");
+        command
+            .env("PATH", &self.path)
+            .args(["-f", "-c", "echo $tcsh"]);
+        direct::version(command, self.wait.get_timeout())
+    }
+
+    /// Register a completion script
+    pub fn register(&mut self, _name: &str, content: &str) -> Result<(), Error> {
+        let mut file = std::fs::OpenOptions::new()
+            .append(true)
+            .open(&self.config)?;
+        writeln!(&mut file, "{content}")?;
+        Ok(())
+    }
+
+    /// Get the output from typing `input` into the shell
+    pub fn complete(&mut self, input: &str, term: &Term) -> Result<String, Error> {
+        self.complete_input(&Input::from(input), term)
+    }
+}
+
+impl PtyShell for TcshRuntime {
+    const ECHO: bool = false;
+
+    fn command(&self) -> Command {
+        let mut command = Command::new("tcsh");
+        command
+            .env("PATH", &self.path)
+            .env("TERM", "xterm")
+            // tcsh only reads `~/.tcshrc`
+            .env("HOME", &self.home);
+        command
+    }
+
+    fn parse(&self, screen: &Screen, _: &Input) -> Completion {
+        parse::tcsh(screen)
+    }
+}
+
+pty_runtime!(TcshRuntime);
+
+/// Abstract factory for [`KshRuntime`]
+#[derive(Debug)]
+#[non_exhaustive]
+pub struct KshRuntimeBuilder {}
+
+impl RuntimeBuilder for KshRuntimeBuilder {
+    type Runtime = KshRuntime;
+
+    fn name() -> &'static str {
+        "ksh"
+    }
+
+    fn is_available() -> Result<(), Unavailable> {
+        find_binary("ksh")
+    }
+
+    fn new(bin_root: PathBuf, home: PathBuf) -> Result<Self::Runtime, Error> {
+        KshRuntime::new(bin_root, home)
+    }
+
+    fn with_home(bin_root: PathBuf, home: PathBuf) -> Result<Self::Runtime, Error> {
+        KshRuntime::with_home(bin_root, home)
+    }
+}
+
+/// Korn shell runtime (ksh93 or mksh, whichever is installed as `ksh`)
+///
+/// ksh has no hook to run before the prompt, so readiness falls back to the startup output going
+/// quiet.
+#[derive(Debug)]
+#[cfg(unix)] // purely for rustdoc to pick it up
+pub struct KshRuntime {
+    path: OsString,
+    home: PathBuf,
+    config: PathBuf,
+    wait: WaitStrategy,
+}
+
+impl KshRuntime {
+    /// Initialize a new runtime's home
+    pub fn new(bin_root: PathBuf, home: PathBuf) -> Result<Self, Error> {
+        std::fs::create_dir_all(&home)?;
+
+        let config_path = home.join(".kshrc");
+        let config = "\
+# ksh has no hook to run before the prompt, but expands it every time
+PS1='$(printf \"\\033]7777;completest-ready\\007\" >/dev/tty)% '
+HISTFILE=/dev/null
+set -o emacs
+"
+        .to_owned();
+        std::fs::write(config_path, config)?;
+
+        Self::with_home(bin_root, home)
+    }
+
+    /// Reuse an existing runtime's home
+    pub fn with_home(bin_root: PathBuf, home: PathBuf) -> Result<Self, Error> {
+        let config_path = home.join(".kshrc");
+        let path = build_path(bin_root);
+
+        Ok(Self {
+            path,
+            home,
+            config: config_path,
+            wait: WaitStrategy::quiet(Duration::from_millis(50)),
+        })
+    }
+
+    /// Location of the runtime's home directory
+    pub fn home(&self) -> &std::path::Path {
+        &self.home
+    }
+
+    /// Version of the shell being run, from `$KSH_VERSION`
+    pub fn version(&self) -> Result<Version, Error> {
+        let mut command = Command::new("ksh");
+        command
             .env("PATH", &self.path)
-            .env("XDG_CONFIG_HOME", &self.home);
-        let echo = false;
-        comptest(command, echo, input, term, self.timeout)
+            .args(["-c", r#"echo "$KSH_VERSION""#]);
+        direct::version(command, self.wait.get_timeout())
+    }
+
+    /// Register a completion script
+    pub fn register(&mut self, _name: &str, content: &str) -> Result<(), Error> {
+        let mut file = std::fs::OpenOptions::new()
+            .append(true)
+            .open(&self.config)?;
+        writeln!(&mut file, "{content}")?;
+        Ok(())
+    }
+
+    /// Get the output from typing `input` into the shell
+    pub fn complete(&mut self, input: &str, term: &Term) -> Result<String, Error> {
+        self.complete_input(&Input::from(input), term)
+    }
+}
+
+impl PtyShell for KshRuntime {
+    const ECHO: bool = true;
+
+    fn command(&self) -> Command {
+        let mut command = Command::new("ksh");
+        command
+            .arg("-i")
+            .env("PATH", &self.path)
+            .env("TERM", "xterm")
+            .env("ENV", &self.config);
+        command
+    }
+
+    fn parse(&self, screen: &Screen, _: &Input) -> Completion {
+        parse::ksh(screen)
+    }
+}
+
+pty_runtime!(KshRuntime);
+
+/// Abstract factory for [`OshRuntime`]
+#[derive(Debug)]
+#[non_exhaustive]
+pub struct OshRuntimeBuilder {}
+
+impl RuntimeBuilder for OshRuntimeBuilder {
+    type Runtime = OshRuntime;
+
+    fn name() -> &'static str {
+        "osh"
+    }
+
+    fn is_available() -> Result<(), Unavailable> {
+        find_binary("osh")
+    }
+
+    fn new(bin_root: PathBuf, home: PathBuf) -> Result<Self::Runtime, Error> {
+        OshRuntime::new(bin_root, home)
+    }
+
+    fn with_home(bin_root: PathBuf, home: PathBuf) -> Result<Self::Runtime, Error> {
+        OshRuntime::with_home(bin_root, home)
+    }
+}
+
+/// Oils runtime, running bash completion scripts in `osh`
+#[derive(Debug)]
+#[cfg(unix)] // purely for rustdoc to pick it up
+pub struct OshRuntime {
+    path: OsString,
+    home: PathBuf,
+    config: PathBuf,
+    wait: WaitStrategy,
+}
+
+impl OshRuntime {
+    /// Initialize a new runtime's home
+    pub fn new(bin_root: PathBuf, home: PathBuf) -> Result<Self, Error> {
+        std::fs::create_dir_all(&home)?;
+
+        let config_path = home.join(".oshrc");
+        let config = "\
+PS1='% '
+PROMPT_COMMAND='printf \"\\033]7777;completest-ready\\007\"'
+"
+        .to_owned();
+        std::fs::write(config_path, config)?;
+
+        Self::with_home(bin_root, home)
+    }
+
+    /// Reuse an existing runtime's home
+    pub fn with_home(bin_root: PathBuf, home: PathBuf) -> Result<Self, Error> {
+        let config_path = home.join(".oshrc");
+        let path = build_path(bin_root);
+
+        Ok(Self {
+            path,
+            home,
+            config: config_path,
+            wait: WaitStrategy::quiet(Duration::from_millis(50)),
+        })
+    }
+
+    /// Location of the runtime's home directory
+    pub fn home(&self) -> &std::path::Path {
+        &self.home
+    }
+
+    /// Version of the shell being run, from `$OILS_VERSION`
+    pub fn version(&self) -> Result<Version, Error> {
+        let mut command = Command::new("osh");
+        command
+            .env("PATH", &self.path)
+            .args(["-c", r#"echo "${OILS_VERSION:-$OIL_VERSION}""#]);
+        direct::version(command, self.wait.get_timeout())
+    }
+
+    /// Register a completion script
+    pub fn register(&mut self, _name: &str, content: &str) -> Result<(), Error> {
+        let mut file = std::fs::OpenOptions::new()
+            .append(true)
+            .open(&self.config)?;
+        writeln!(&mut file, "{content}")?;
+        Ok(())
+    }
+
+    /// Get the output from typing `input` into the shell
+    pub fn complete(&mut self, input: &str, term: &Term) -> Result<String, Error> {
+        self.complete_input(&Input::from(input), term)
+    }
+}
+
+impl PtyShell for OshRuntime {
+    const ECHO: bool = true;
+
+    fn command(&self) -> Command {
+        let mut command = Command::new("osh");
+        command.env("PATH", &self.path).env("TERM", "xterm").args([
+            OsStr::new("--rcfile"),
+            self.config.as_os_str(),
+            // List candidates and redraw the prompt like bash
+            OsStr::new("--completion-display"),
+            OsStr::new("minimal"),
+            OsStr::new("-i"),
+        ]);
+        command
+    }
+
+    fn parse(&self, screen: &Screen, _: &Input) -> Completion {
+        parse::osh(screen)
+    }
+}
+
+pty_runtime!(OshRuntime);
+
+/// Abstract factory for [`NuPtyRuntime`]
+#[derive(Debug)]
+#[non_exhaustive]
+pub struct NuPtyRuntimeBuilder {}
+
+impl RuntimeBuilder for NuPtyRuntimeBuilder {
+    type Runtime = NuPtyRuntime;
+
+    fn name() -> &'static str {
+        "nu-pty"
+    }
+
+    fn is_available() -> Result<(), Unavailable> {
+        find_binary("nu")
+    }
+
+    fn new(bin_root: PathBuf, home: PathBuf) -> Result<Self::Runtime, Error> {
+        NuPtyRuntime::new(bin_root, home)
+    }
+
+    fn with_home(bin_root: PathBuf, home: PathBuf) -> Result<Self::Runtime, Error> {
+        NuPtyRuntime::with_home(bin_root, home)
+    }
+}
+
+/// Nushell runtime, driving the installed `nu` binary
+#[derive(Debug)]
+#[cfg(unix)] // purely for rustdoc to pick it up
+pub struct NuPtyRuntime {
+    path: OsString,
+    home: PathBuf,
+    config: PathBuf,
+    env_config: PathBuf,
+    wait: WaitStrategy,
+}
+
+impl NuPtyRuntime {
+    /// Initialize a new runtime's home
+    pub fn new(bin_root: PathBuf, home: PathBuf) -> Result<Self, Error> {
+        std::fs::create_dir_all(&home)?;
+
+        let config_path = home.join(".config/nushell/config.nu");
+        std::fs::create_dir_all(config_path.parent().expect("path created with parent"))?;
+        let config = "\
+$env.config = {
+    show_banner: false
+    hooks: {
+        pre_prompt: [{|| print -n $\"(char esc)]7777;completest-ready(char bel)\" }]
+    }
+}
+";
+        std::fs::write(config_path, config)?;
+
+        let env_config_path = home.join(".config/nushell/env.nu");
+        let env_config = "\
+$env.PROMPT_COMMAND = {|| '% ' }
+$env.PROMPT_COMMAND_RIGHT = {|| '' }
+$env.PROMPT_INDICATOR = {|| '' }
+";
+        std::fs::write(env_config_path, env_config)?;
+
+        Self::with_home(bin_root, home)
+    }
+
+    /// Reuse an existing runtime's home
+    pub fn with_home(bin_root: PathBuf, home: PathBuf) -> Result<Self, Error> {
+        let config_path = home.join(".config/nushell/config.nu");
+        let env_config_path = home.join(".config/nushell/env.nu");
+        let path = build_path(bin_root);
+
+        Ok(Self {
+            path,
+            home,
+            config: config_path,
+            env_config: env_config_path,
+            wait: WaitStrategy::quiet(Duration::from_millis(100)),
+        })
+    }
+
+    /// Location of the runtime's home directory
+    pub fn home(&self) -> &std::path::Path {
+        &self.home
+    }
+
+    /// Version of the shell being run, from `version`
+    pub fn version(&self) -> Result<Version, Error> {
+        let mut command = Command::new("nu");
+        command
+            .env("PATH", &self.path)
+            .args(["-c", "(version).version"]);
+        direct::version(command, self.wait.get_timeout())
+    }
+
+    /// Register a completion script
+    ///
+    /// `content` is sourced from `config.nu`, like for `completest_nu::NuRuntime`.
+    pub fn register(&mut self, name: &str, content: &str) -> Result<(), Error> {
+        let path = self
+            .home
+            .join(format!(".config/nushell/completions/{name}.nu"));
+        std::fs::create_dir_all(path.parent().expect("path created with parent"))?;
+        std::fs::write(&path, content)?;
+
+        let mut file = std::fs::OpenOptions::new()
+            .append(true)
+            .open(&self.config)?;
+        writeln!(&mut file, "source `{}`", path.display())?;
+        Ok(())
+    }
+
+    /// Get the output from typing `input` into the shell
+    pub fn complete(&mut self, input: &str, term: &Term) -> Result<String, Error> {
+        self.complete_input(&Input::from(input), term)
+    }
+}
+
+impl PtyShell for NuPtyRuntime {
+    const ECHO: bool = false;
+
+    fn command(&self) -> Command {
+        let mut command = Command::new("nu");
+        command
+            .env("PATH", &self.path)
+            .env("TERM", "xterm")
+            .env("HOME", &self.home)
+            .env("XDG_CONFIG_HOME", self.home.join(".config"))
+            .args([
+                OsStr::new("--config"),
+                self.config.as_os_str(),
+                OsStr::new("--env-config"),
+                self.env_config.as_os_str(),
+            ]);
+        command
+    }
+
+    fn parse(&self, screen: &Screen, _: &Input) -> Completion {
+        parse::nu(screen)
     }
 }
 
-impl Runtime for ElvishRuntime {
-    fn home(&self) -> &std::path::Path {
-        self.home()
+pty_runtime!(NuPtyRuntime);
+
+/// Abstract factory for [`BashCompgenRuntime`]
+#[derive(Debug)]
+#[non_exhaustive]
+pub struct BashCompgenRuntimeBuilder {}
+
+impl RuntimeBuilder for BashCompgenRuntimeBuilder {
+    type Runtime = BashCompgenRuntime;
+
+    fn name() -> &'static str {
+        "bash-compgen"
+    }
+
+    fn is_available() -> Result<(), Unavailable> {
+        find_binary("bash")
     }
 
-    fn register(&mut self, name: &str, content: &str) -> std::io::Result<()> {
-        self.register(name, content)
+    fn new(bin_root: PathBuf, home: PathBuf) -> Result<Self::Runtime, Error> {
+        BashCompgenRuntime::new(bin_root, home)
     }
 
-    fn complete(&mut self, input: &str, term: &Term) -> std::io::Result<String> {
-        self.complete(input, term)
+    fn with_home(bin_root: PathBuf, home: PathBuf) -> Result<Self::Runtime, Error> {
+        BashCompgenRuntime::with_home(bin_root, home)
     }
 }
 
-fn comptest(
-    command: Command,
-    echo: bool,
-    input: &str,
-    term: &Term,
+/// Bash runtime, calling the registered completion function directly rather than through a terminal
+///
+/// The completion function is given `COMP_WORDS`, `COMP_CWORD`, `COMP_LINE`, and `COMP_POINT`
+/// for the input, and `COMPREPLY` is reported as is, without bash's sorting or filtering.  Specs
+/// without `complete -F` are run through `compgen`.
+#[derive(Debug)]
+#[cfg(unix)] // purely for rustdoc to pick it up
+pub struct BashCompgenRuntime {
+    path: OsString,
+    home: PathBuf,
     timeout: Duration,
-) -> std::io::Result<String> {
-    #![allow(clippy::unwrap_used)] // some unwraps need extra investigation
-
-    // spawn a new process, pass it the input was.
-    //
-    // This triggers completion loading process which takes some time in shell so we should let it
-    // run for some time
-    let mut process = PtyProcess::spawn(command)?;
-    process.set_window_size(term.get_width(), term.get_height())?;
-    // for some reason bash does not produce anything with echo disabled...
-    process.set_echo(echo, None)?;
-
-    let mut parser = vt100::Parser::new(term.get_height(), term.get_width(), 0);
-
-    let mut stream = process.get_raw_handle()?;
-    // pass the completion input
-    write!(stream, "{input}")?;
-    stream.flush()?;
-
-    let (snd, rcv) = std::sync::mpsc::channel();
-
-    let shutdown = std::sync::atomic::AtomicBool::new(false);
-    let shutdown_ref = &shutdown;
-    std::thread::scope(|scope| {
-        scope.spawn(move || {
-            // since we don't know when exactly shell is done completing the idea is to wait until
-            // something at all is produced, then wait for some duration since the last produced chunk.
-            rcv.recv().unwrap();
-            while rcv.recv_timeout(timeout).is_ok() {}
-            shutdown_ref.store(true, std::sync::atomic::Ordering::SeqCst);
-            process.exit(false).unwrap();
-        });
-
-        let mut buf = [0; 2048];
-        while let Ok(n) = stream.read(&mut buf) {
-            if shutdown.load(std::sync::atomic::Ordering::SeqCst) {
-                // fish clears completions on process teardown
-                break;
-            }
-            let buf = &buf[..n];
-            if buf.is_empty() {
-                break;
+}
+
+impl BashCompgenRuntime {
+    /// Initialize a new runtime's home
+    pub fn new(bin_root: PathBuf, home: PathBuf) -> Result<Self, Error> {
+        std::fs::create_dir_all(home.join("bash"))?;
+
+        Self::with_home(bin_root, home)
+    }
+
+    /// Reuse an existing runtime's home
+    pub fn with_home(bin_root: PathBuf, home: PathBuf) -> Result<Self, Error> {
+        let path = build_path(bin_root);
+
+        Ok(Self {
+            path,
+            home,
+            timeout: Duration::from_secs(10),
+        })
+    }
+
+    /// Location of the runtime's home directory
+    pub fn home(&self) -> &std::path::Path {
+        &self.home
+    }
+
+    /// Version of the shell being run, from `$BASH_VERSION`
+    pub fn version(&self) -> Result<Version, Error> {
+        direct::version(bash_version(&self.path), self.timeout)
+    }
+
+    /// Register a completion script
+    pub fn register(&mut self, name: &str, content: &str) -> Result<(), Error> {
+        let path = self.home.join(format!("bash/{name}.bash"));
+        std::fs::create_dir_all(path.parent().expect("path created with parent"))?;
+        std::fs::write(path, content)?;
+        Ok(())
+    }
+
+    /// Get the candidates for `input`, one per line
+    pub fn complete(&mut self, input: &str, term: &Term) -> Result<String, Error> {
+        self.complete_input(&Input::from(input), term)
+    }
+}
+
+impl DirectShell for BashCompgenRuntime {
+    fn completion(&self, line: String, cursor: usize) -> Result<Completion, Error> {
+        let (words, cword, cur) = direct::bash_words(&line, cursor);
+        let prev = cword.checked_sub(1).map(|i| words[i]).unwrap_or_default();
+
+        let mut command = Command::new("bash");
+        command
+            .env("PATH", &self.path)
+            .env("COMPLETEST_HOME", &self.home)
+            .args([
+                "--noprofile",
+                "--norc",
+                "-c",
+                BASH_COMPGEN_DRIVER,
+                "completest",
+            ])
+            .arg(cword.to_string())
+            .arg(line[..cursor].chars().count().to_string())
+            .args([line.as_str(), cur, prev])
+            .args(&words);
+        let output = direct::run(command, self.timeout)?;
+
+        let candidates: Vec<_> = direct::records(&output)
+            .into_iter()
+            .map(|[value]| Candidate::new(value))
+            .collect();
+        Ok(Completion::new(direct::screen(&candidates))
+            .buffer(line)
+            .cursor(cursor)
+            .candidates(candidates))
+    }
+}
+
+direct_runtime!(BashCompgenRuntime);
+
+/// Emulates what bash does on `Tab`, printing `COMPREPLY` NUL-separated
+///
+/// Called with `COMP_CWORD`, `COMP_POINT`, `COMP_LINE`, the word being completed, the word before
+/// it, and then `COMP_WORDS`.
+const BASH_COMPGEN_DRIVER: &str = r#"
+completest_cword=$1 completest_point=$2 completest_line=$3 completest_cur=$4 completest_prev=$5
+shift 5
+completest_words=("$@")
+set --
+
+for completest_script in "$COMPLETEST_HOME"/bash/*.bash; do
+    [[ -e $completest_script ]] && source "$completest_script"
+done
+
+completest_spec=$(complete -p -- "${completest_words[0]}" 2>/dev/null) || {
+    printf 'no completion registered for `%s`\n' "${completest_words[0]}" >&2
+    exit 1
+}
+eval "completest_spec=($completest_spec)"
+
+COMP_WORDS=("${completest_words[@]}")
+COMP_CWORD=$completest_cword
+COMP_LINE=$completest_line
+COMP_POINT=$completest_point
+COMP_TYPE=9
+COMP_KEY=9
+COMPREPLY=()
+
+completest_function=
+for ((completest_i = 1; completest_i + 1 < ${#completest_spec[@]}; completest_i++)); do
+    if [[ ${completest_spec[completest_i]} == -F ]]; then
+        completest_function=${completest_spec[completest_i + 1]}
+    fi
+done
+if [[ -n $completest_function ]]; then
+    "$completest_function" "${COMP_WORDS[0]}" "$completest_cur" "$completest_prev"
+else
+    # Everything between `complete` and the command name
+    readarray -t COMPREPLY < <(
+        compgen "${completest_spec[@]:1:${#completest_spec[@]} - 2}" -- "$completest_cur"
+    )
+fi
+
+if ((${#COMPREPLY[@]})); then
+    printf '%s\0' "${COMPREPLY[@]}"
+fi
+"#;
+
+/// Whether `input` asks bash to list the candidates
+fn double_tab(input: &Input) -> bool {
+    let tab = InputEvent::Key(Key::Tab);
+    input
+        .get_events()
+        .windows(2)
+        .any(|pair| pair[0] == tab && pair[1] == tab)
+}
+
+/// Command-line up to the cursor, as typed by `input` before any keys are pressed
+fn typed(input: &Input) -> String {
+    let mut line = String::new();
+    let mut cursor = 0;
+    for event in input.get_events() {
+        match event {
+            InputEvent::Text(text) => {
+                line.insert_str(cursor, text);
+                cursor += text.len();
             }
-            let _ = snd.send(());
-            parser.process(buf);
+            InputEvent::Cursor(offset) => cursor = session::floor_char_boundary(&line, *offset),
+            _ => {}
         }
+    }
+    line.truncate(cursor);
+    line
+}
+
+/// Command printing zsh's version, from `$ZSH_VERSION`
+fn zsh_version(path: &OsStr) -> Command {
+    let mut command = Command::new("zsh");
+    command
+        .env("PATH", path)
+        .args(["-f", "-c", "print -r -- $ZSH_VERSION"]);
+    command
+}
+
+/// Command printing bash's version, from `$BASH_VERSION`
+fn bash_version(path: &OsStr) -> Command {
+    let mut command = Command::new("bash");
+    command.env("PATH", path).args([
+        "--noprofile",
+        "--norc",
+        "-c",
+        r#"printf '%s\n' "$BASH_VERSION""#,
+    ]);
+    command
+}
+
+/// Command printing fish's version, from `$FISH_VERSION`
+fn fish_version(path: &OsStr, home: &std::path::Path) -> Command {
+    let mut command = Command::new("fish");
+    command
+        .env("PATH", path)
+        .env("XDG_CONFIG_HOME", home)
+        .args(["-c", "echo $FISH_VERSION"]);
+    command
+}
+
+/// Command printing elvish's version, from `elvish -version`
+fn elvish_version(path: &OsStr) -> Command {
+    let mut command = Command::new("elvish");
+    command.env("PATH", path).args(["-version"]);
+    command
+}
+
+/// Look for `name` on the `PATH`, like spawning the shell will
+fn find_binary(name: &str) -> Result<(), Unavailable> {
+    use std::os::unix::fs::PermissionsExt as _;
+
+    let path = std::env::var_os("PATH").unwrap_or_default();
+    let found = std::env::split_paths(&path).any(|dir| {
+        dir.join(name)
+            .metadata()
+            .is_ok_and(|meta| meta.is_file() && meta.permissions().mode() & 0o111 != 0)
     });
+    if found {
+        Ok(())
+    } else {
+        Err(Unavailable::new(format!("`{name}` is not installed")))
+    }
+}
 
-    let content = parser.screen().contents();
-    Ok(content)
+/// Look for a file the runtime loads, provided by `package`
+fn find_file(path: &str, package: &str) -> Result<(), Unavailable> {
+    if std::path::Path::new(path).exists() {
+        Ok(())
+    } else {
+        Err(Unavailable::new(format!(
+            "`{path}` is missing, install `{package}`"
+        )))
+    }
 }
 
 fn build_path(bin_root: PathBuf) -> OsString {
@@ -0,0 +1,420 @@
+//! Recover [`Completion`]s from what each shell rendered
+
+use crate::Candidate;
+use crate::Completion;
+use crate::Screen;
+
+/// Prompt every runtime is configured with
+const PROMPT: &str = "% ";
+
+/// Bash lists candidates in columns and then redraws the prompt below them
+///
+/// With `menu`, the command-line typed up to the cursor, `menu-complete` cycles through candidates on the
+/// command-line rather than highlighting them.
+pub(crate) fn bash(screen: &Screen, menu: Option<&str>) -> Completion {
+    let lines = Lines::new(screen);
+    let mut candidates = Vec::new();
+    for row in lines.redrawn_listing() {
+        candidates.extend(columns(row).map(Candidate::new));
+    }
+    let completion = lines.completion(screen, candidates);
+    let Some(typed) = menu else {
+        return completion;
+    };
+    // `menu-complete` replaces the word being completed with a candidate followed by a space, and
+    // leaves it alone when nothing matches
+    let start = typed.rfind(' ').map(|i| i + 1).unwrap_or_default();
+    let before_cursor = lines.before_cursor();
+    match before_cursor.get(start..).map(str::trim_end) {
+        Some(inserted) if !inserted.is_empty() && inserted != &typed[start..] => {
+            completion.highlighted(inserted)
+        }
+        _ => completion,
+    }
+}
+
+/// Zsh lists candidates below the prompt, with descriptions after ` -- `
+pub(crate) fn zsh(screen: &Screen) -> Completion {
+    let lines = Lines::new(screen);
+    let mut candidates = Vec::new();
+    for row in lines.listing() {
+        if let Some((values, description)) = row.split_once(" -- ") {
+            let description = description.trim();
+            candidates.extend(
+                columns(values).map(|value| Candidate::new(value).description(description)),
+            );
+        } else {
+            candidates.extend(columns(row).map(Candidate::new));
+        }
+    }
+    lines.completion(screen, candidates)
+}
+
+/// Fish's pager lists candidates below the prompt, with descriptions in parenthesis
+pub(crate) fn fish(screen: &Screen) -> Completion {
+    let lines = Lines::new(screen);
+    let mut candidates = Vec::new();
+    for row in lines.listing() {
+        if row.starts_with("rows ") || row.starts_with('…') {
+            // pager status line
+            continue;
+        }
+        // Multiple values may share a description
+        let mut values = Vec::new();
+        for cell in columns(row) {
+            if let Some(description) = cell.strip_prefix('(').and_then(|d| d.strip_suffix(')')) {
+                candidates.extend(
+                    values
+                        .drain(..)
+                        .map(|value| Candidate::new(value).description(description)),
+                );
+            } else {
+                values.push(cell);
+            }
+        }
+        candidates.extend(values.drain(..).map(Candidate::new));
+    }
+    lines.completion(screen, candidates)
+}
+
+/// Elvish's completion mode lists candidates below the prompt under a `COMPLETING <type>` header
+pub(crate) fn elvish(screen: &Screen) -> Completion {
+    let lines = Lines::new(screen);
+    let mut group = None;
+    let mut candidates = Vec::new();
+    for row in lines.listing() {
+        if let Some(header) = row.trim().strip_prefix("COMPLETING ") {
+            group = Some(header.trim().to_owned());
+            continue;
+        }
+        candidates.extend(columns(row).map(|value| {
+            let candidate = Candidate::new(value);
+            match &group {
+                Some(group) => candidate.group(group),
+                None => candidate,
+            }
+        }));
+    }
+    lines.completion(screen, candidates)
+}
+
+/// PowerShell's `MenuComplete` lists candidates in columns below the prompt
+pub(crate) fn pwsh(screen: &Screen) -> Completion {
+    listing(screen)
+}
+
+/// Xonsh's completion menu lists candidates in columns below the prompt
+pub(crate) fn xonsh(screen: &Screen) -> Completion {
+    listing(screen)
+}
+
+/// tcsh's `autolist` lists candidates in columns and then redraws the prompt below them, like bash
+pub(crate) fn tcsh(screen: &Screen) -> Completion {
+    bash(screen, None)
+}
+
+/// ksh lists candidates and then redraws the prompt below them, like bash
+///
+/// ksh93 numbers each candidate, like `1) alpha`.
+pub(crate) fn ksh(screen: &Screen) -> Completion {
+    let lines = Lines::new(screen);
+    let mut candidates = Vec::new();
+    for row in lines.redrawn_listing() {
+        candidates.extend(columns(row).map(|cell| {
+            let value = match cell.split_once(") ") {
+                Some((number, value)) if number.chars().all(|c| c.is_ascii_digit()) => value,
+                _ => cell,
+            };
+            Candidate::new(value)
+        }));
+    }
+    lines.completion(screen, candidates)
+}
+
+/// osh lists candidates like bash with `--completion-display minimal`
+pub(crate) fn osh(screen: &Screen) -> Completion {
+    bash(screen, None)
+}
+
+/// Nushell's completion menu lists candidates in columns below the prompt, highlighting the
+/// selected one
+pub(crate) fn nu(screen: &Screen) -> Completion {
+    listing(screen)
+}
+
+/// Command-line the cursor is on
+pub(crate) fn buffer(screen: &Screen) -> String {
+    Lines::new(screen).buffer()
+}
+
+/// Byte offset of the cursor in the [`buffer`]
+pub(crate) fn cursor(screen: &Screen) -> usize {
+    Lines::new(screen).before_cursor().len()
+}
+
+/// Candidates in columns below the command-line
+fn listing(screen: &Screen) -> Completion {
+    let lines = Lines::new(screen);
+    let mut candidates = Vec::new();
+    for row in lines.listing() {
+        candidates.extend(columns(row).map(Candidate::new));
+    }
+    lines.completion(screen, candidates)
+}
+
+struct Lines {
+    rows: Vec<String>,
+    cursor_row: usize,
+    cursor_col: usize,
+}
+
+impl Lines {
+    fn new(screen: &Screen) -> Self {
+        let (_, width) = screen.size();
+        let rows = screen.rows(0, width).collect();
+        let (cursor_row, cursor_col) = screen.cursor_position();
+        Self {
+            rows,
+            cursor_row: usize::from(cursor_row),
+            cursor_col: usize::from(cursor_col),
+        }
+    }
+
+    /// Rows below the command-line
+    fn listing(&self) -> impl Iterator<Item = &str> {
+        self.rows
+            .iter()
+            .skip(self.cursor_row + 1)
+            .map(|row| row.as_str())
+            .filter(|row| !row.trim().is_empty())
+    }
+
+    /// Rows between the original command-line and where the shell redrew it
+    fn redrawn_listing(&self) -> impl Iterator<Item = &str> {
+        let first_prompt = self
+            .rows
+            .iter()
+            .position(|row| row.contains(PROMPT))
+            .unwrap_or(0);
+        self.rows
+            .iter()
+            .take(self.cursor_row)
+            .skip(first_prompt + 1)
+            .map(|row| row.as_str())
+            .filter(|row| !row.contains(PROMPT))
+    }
+
+    /// Command-line the cursor is on
+    fn buffer(&self) -> String {
+        let Some(row) = self.rows.get(self.cursor_row) else {
+            return String::new();
+        };
+        let mut buffer = row.clone();
+        // The screen doesn't distinguish trailing whitespace from empty cells
+        let len = buffer.chars().count();
+        if len < self.cursor_col {
+            buffer.extend(std::iter::repeat_n(' ', self.cursor_col - len));
+        }
+        strip_prompt(buffer)
+    }
+
+    /// Command-line up to the cursor
+    fn before_cursor(&self) -> String {
+        let Some(row) = self.rows.get(self.cursor_row) else {
+            return String::new();
+        };
+        let buffer = row
+            .chars()
+            .chain(std::iter::repeat(' '))
+            .take(self.cursor_col)
+            .collect();
+        strip_prompt(buffer)
+    }
+
+    fn completion(&self, screen: &Screen, candidates: Vec<Candidate>) -> Completion {
+        let completion = Completion::new(screen.contents())
+            .buffer(self.buffer())
+            .cursor(self.before_cursor().len())
+            .candidates(candidates);
+        match highlighted(screen) {
+            Some(highlighted) => completion.highlighted(highlighted),
+            None => completion,
+        }
+    }
+}
+
+/// Candidate a menu has selected, drawn in reverse video below the command-line
+fn highlighted(screen: &Screen) -> Option<String> {
+    let (rows, width) = screen.size();
+    let (cursor_row, _) = screen.cursor_position();
+    for row in cursor_row + 1..rows {
+        let mut selected = String::new();
+        for col in 0..width {
+            let Some(cell) = screen.cell(row, col) else {
+                continue;
+            };
+            if cell.inverse() {
+                let contents = cell.contents();
+                selected.push_str(if contents.is_empty() { " " } else { contents });
+            } else if !selected.is_empty() {
+                break;
+            }
+        }
+        let value = selected.split(" -- ").next().unwrap_or_default();
+        if let Some(value) = columns(value).next() {
+            return Some(value.to_owned());
+        }
+    }
+    None
+}
+
+fn strip_prompt(mut line: String) -> String {
+    match line.rfind(PROMPT) {
+        Some(start) => line.split_off(start + PROMPT.len()),
+        None => line,
+    }
+}
+
+/// Cells of a listing, separated by at least two spaces
+fn columns(row: &str) -> impl Iterator<Item = &str> {
+    row.split("  ")
+        .map(|cell| cell.trim())
+        .filter(|cell| !cell.is_empty())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn screen(bytes: &[u8]) -> Screen {
+        let mut parser = vt100::Parser::new(24, 80, 0);
+        parser.process(bytes);
+        parser.screen().clone()
+    }
+
+    fn values(completion: &Completion) -> Vec<&str> {
+        completion
+            .get_candidates()
+            .iter()
+            .map(|candidate| candidate.get_value())
+            .collect()
+    }
+
+    #[test]
+    fn bash_listing() {
+        let screen = screen(b"% foo --co\r\n--color  --count  \r\n% foo --co");
+        let completion = bash(&screen, None);
+        assert_eq!(values(&completion), ["--color", "--count"]);
+        assert_eq!(completion.get_buffer(), "foo --co");
+        assert_eq!(completion.get_cursor(), 8);
+        assert_eq!(completion.get_highlighted(), None);
+    }
+
+    #[test]
+    fn bash_listing_ignores_startup_output() {
+        let screen = screen(b"starting\r\n% foo \r\n--help     --version  alpha      \r\n% foo ");
+        let completion = bash(&screen, None);
+        assert_eq!(values(&completion), ["--help", "--version", "alpha"]);
+        assert_eq!(completion.get_buffer(), "foo ");
+    }
+
+    #[test]
+    fn bash_menu() {
+        let screen = screen(b"% foo --color ");
+        let completion = bash(&screen, Some("foo --co"));
+        assert_eq!(completion.get_highlighted(), Some("--color"));
+        assert_eq!(completion.get_buffer(), "foo --color ");
+        assert_eq!(completion.get_cursor(), 12);
+    }
+
+    #[test]
+    fn bash_menu_without_match() {
+        let completion = bash(&screen(b"% foo --zz"), Some("foo --zz"));
+        assert_eq!(completion.get_highlighted(), None);
+
+        let completion = bash(&screen(b"% foo "), Some("foo "));
+        assert_eq!(completion.get_highlighted(), None);
+    }
+
+    #[test]
+    fn zsh_descriptions() {
+        let screen = screen(
+            b"% foo --\r\n--color  -- use colors\r\n--count  -- count things\r\n--help  --version\x1b[1;9H",
+        );
+        let completion = zsh(&screen);
+        assert_eq!(
+            completion.get_candidates(),
+            [
+                Candidate::new("--color").description("use colors"),
+                Candidate::new("--count").description("count things"),
+                Candidate::new("--help"),
+                Candidate::new("--version"),
+            ]
+        );
+        assert_eq!(completion.get_buffer(), "foo --");
+        assert_eq!(completion.get_cursor(), 6);
+    }
+
+    #[test]
+    fn fish_shared_descriptions() {
+        let screen = screen(
+            b"% foo -\r\n-h  --help  (Print help)  --color  (Use colors)\r\nrows 1 to 1 of 1\x1b[1;8H",
+        );
+        let completion = fish(&screen);
+        assert_eq!(
+            completion.get_candidates(),
+            [
+                Candidate::new("-h").description("Print help"),
+                Candidate::new("--help").description("Print help"),
+                Candidate::new("--color").description("Use colors"),
+            ]
+        );
+        assert_eq!(completion.get_buffer(), "foo -");
+    }
+
+    #[test]
+    fn elvish_groups() {
+        let screen = screen(b"% foo \r\n COMPLETING argument \r\n--color  --count\x1b[1;7H");
+        let completion = elvish(&screen);
+        assert_eq!(
+            completion.get_candidates(),
+            [
+                Candidate::new("--color").group("argument"),
+                Candidate::new("--count").group("argument"),
+            ]
+        );
+        assert_eq!(completion.get_buffer(), "foo ");
+    }
+
+    #[test]
+    fn listing_highlighted() {
+        let screen = screen(b"% foo --color\r\n\x1b[7m--color\x1b[0m  --count\x1b[1;14H");
+        for completion in [pwsh(&screen), xonsh(&screen), nu(&screen)] {
+            assert_eq!(values(&completion), ["--color", "--count"]);
+            assert_eq!(completion.get_highlighted(), Some("--color"));
+            assert_eq!(completion.get_buffer(), "foo --color");
+        }
+    }
+
+    #[test]
+    fn ksh_numbered() {
+        let screen = screen(b"% foo --\r\n1) --color\r\n2) --count\r\n% foo --");
+        let completion = ksh(&screen);
+        assert_eq!(values(&completion), ["--color", "--count"]);
+        assert_eq!(completion.get_buffer(), "foo --");
+    }
+
+    #[test]
+    fn cursor_mid_line() {
+        let screen = screen(b"% foo --co bar\x1b[1;11H");
+        assert_eq!(buffer(&screen), "foo --co bar");
+        assert_eq!(cursor(&screen), 8);
+    }
+
+    #[test]
+    fn trailing_whitespace() {
+        let screen = screen(b"% foo --help ");
+        assert_eq!(buffer(&screen), "foo --help ");
+        assert_eq!(cursor(&screen), 11);
+    }
+}
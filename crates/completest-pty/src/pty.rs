@@ -0,0 +1,143 @@
+//! Support for runtimes that type into an interactive shell over a PTY
+
+use std::process::Command;
+use std::time::Instant;
+
+use crate::Completion;
+use crate::Error;
+use crate::Input;
+use crate::Screen;
+use crate::Session;
+use crate::Term;
+use crate::WaitStrategy;
+
+/// What sets apart the shells driven over a PTY
+///
+/// [`pty_runtime!`] provides the rest of the runtime from this.
+pub(crate) trait PtyShell {
+    /// Whether the PTY echoes what is typed
+    const ECHO: bool;
+
+    /// Command to start the interactive shell
+    fn command(&self) -> Command;
+
+    /// Whether the PTY echoes what is typed when reporting the raw output for `input`
+    fn complete_echo(&self, _input: &Input) -> bool {
+        Self::ECHO
+    }
+
+    /// Recover the [`Completion`] from what the shell rendered for `input`
+    fn parse(&self, screen: &Screen, input: &Input) -> Completion;
+}
+
+/// Start `shell` and wait for it to be ready for input
+pub(crate) fn session<S: PtyShell>(
+    shell: &S,
+    term: &Term,
+    wait: &WaitStrategy,
+) -> Result<Session, Error> {
+    let deadline = Instant::now() + wait.get_timeout();
+    Session::spawn(shell.command(), S::ECHO, term, deadline)
+}
+
+/// Type `input` into a new `shell` and capture the screen once it is done according to `wait`
+pub(crate) fn screen<S: PtyShell>(
+    shell: &S,
+    input: &Input,
+    term: &Term,
+    wait: &WaitStrategy,
+    echo: bool,
+) -> Result<Screen, Error> {
+    // spawn a new process, pass it the input was.
+    //
+    // This triggers completion loading process which takes some time in shell so we should let it
+    // run for some time
+    let deadline = Instant::now() + wait.get_timeout();
+    let mut session = Session::spawn(shell.command(), echo, term, deadline)?;
+
+    // pass the completion input
+    session.send_input(input)?;
+    session.wait_until(wait, deadline)?;
+
+    Ok(session.screen())
+}
+
+/// Provide the [`WaitStrategy`] accessors, [`Session`], and [`Runtime`][crate::Runtime] for a
+/// [`PtyShell`]
+///
+/// The runtime is expected to have a `wait: WaitStrategy` field and inherent `home`, `version`,
+/// and `register` methods.
+macro_rules! pty_runtime {
+    ($runtime:ty) => {
+        impl $runtime {
+            /// Strategy for deciding when the shell is done completing
+            pub fn get_wait(&self) -> &WaitStrategy {
+                &self.wait
+            }
+
+            /// Change the strategy for deciding when the shell is done completing
+            pub fn set_wait(&mut self, wait: WaitStrategy) {
+                self.wait = wait;
+            }
+
+            /// Get the output from typing `input` into the shell, overriding the [`WaitStrategy`]
+            pub fn complete_with(
+                &mut self,
+                input: &Input,
+                term: &Term,
+                wait: &WaitStrategy,
+            ) -> Result<String, Error> {
+                let echo = crate::pty::PtyShell::complete_echo(self, input);
+                crate::pty::screen(self, input, term, wait, echo).map(|screen| screen.contents())
+            }
+
+            /// Get the candidates offered by the shell for `input`, overriding the
+            /// [`WaitStrategy`]
+            pub fn completion_with(
+                &mut self,
+                input: &Input,
+                term: &Term,
+                wait: &WaitStrategy,
+            ) -> Result<Completion, Error> {
+                let echo = <Self as crate::pty::PtyShell>::ECHO;
+                crate::pty::screen(self, input, term, wait, echo)
+                    .map(|screen| crate::pty::PtyShell::parse(self, &screen, input))
+            }
+
+            /// Start the shell for scripting multi-step interactions
+            ///
+            /// Waits for the shell to be ready for input, up to the [`WaitStrategy::timeout`].
+            pub fn session(&self, term: &Term) -> Result<Session, Error> {
+                crate::pty::session(self, term, &self.wait)
+            }
+        }
+
+        impl Runtime for $runtime {
+            fn home(&self) -> &std::path::Path {
+                self.home()
+            }
+
+            fn version(&self) -> Result<Version, Error> {
+                self.version()
+            }
+
+            fn register(&mut self, name: &str, content: &str) -> Result<(), Error> {
+                self.register(name, content)
+            }
+
+            fn complete_input(&mut self, input: &Input, term: &Term) -> Result<String, Error> {
+                let wait = self.wait.clone();
+                self.complete_with(input, term, &wait)
+            }
+
+            fn completion_input(
+                &mut self,
+                input: &Input,
+                term: &Term,
+            ) -> Result<Completion, Error> {
+                let wait = self.wait.clone();
+                self.completion_with(input, term, &wait)
+            }
+        }
+    };
+}
@@ -0,0 +1,465 @@
+use std::io::Read as _;
+use std::io::Write as _;
+use std::os::fd::AsRawFd as _;
+use std::process::Command;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::sync::atomic::AtomicBool;
+use std::sync::atomic::Ordering;
+use std::sync::mpsc::Receiver;
+use std::sync::mpsc::Sender;
+use std::time::Duration;
+use std::time::Instant;
+
+use ptyprocess::PtyProcess;
+
+use crate::Error;
+use crate::Input;
+use crate::InputEvent;
+use crate::Key;
+use crate::Screen;
+use crate::Term;
+use crate::WaitStrategy;
+use crate::wait::Interrupted;
+
+// Marker the shells print before drawing their prompt (`\e]7777;completest-ready\a`)
+//
+// This is an OSC sequence that `vt100` doesn't render, so it won't show up in the screen.
+const SENTINEL_CODE: &[u8] = b"7777";
+const READY_SENTINEL_PAYLOAD: &[u8] = b"completest-ready";
+// Marker the shells that can report it print after completing (`\e]7777;completest-done\a`)
+const DONE_SENTINEL_PAYLOAD: &[u8] = b"completest-done";
+// How long startup output must go quiet before giving up on the sentinel
+//
+// Homes from before the sentinel was printed never report it.  This has to outlast pauses in the
+// rc files, like a slow `compinit`.
+const READY_FALLBACK: Duration = Duration::from_secs(1);
+// How long to wait for the prompt to go quiet after the sentinel
+//
+// The sentinel is printed before the line editor takes over the terminal, so anything typed right
+// away is echoed by the terminal and key sequences can be mangled.
+const READY_SETTLE: Duration = Duration::from_millis(20);
+// How often the reader checks whether it should stop
+const POLL_INTERVAL_MS: i32 = 10;
+
+/// Interactive shell that stays alive between inputs
+///
+/// Unlike [`Runtime::complete`][crate::Runtime::complete], this lets you script multiple steps
+/// against the same shell, like completing, accepting the completion, and completing the next
+/// word.
+///
+/// The shell is killed when the session is dropped.
+///
+/// # Example
+///
+/// ```rust,no_run
+/// # use std::path::Path;
+/// # let bin_root = Path::new("").to_owned();
+/// # let home = std::env::current_dir().unwrap();
+/// use completest_pty::Key;
+/// use completest_pty::WaitStrategy;
+///
+/// let term = completest_pty::Term::new();
+/// let wait = WaitStrategy::quiet(std::time::Duration::from_millis(100));
+///
+/// let runtime = completest_pty::BashRuntime::new(bin_root, home).unwrap();
+/// let mut session = runtime.session(&term).unwrap();
+/// session.send("foo --he").unwrap();
+/// session.send_key(Key::Tab).unwrap();
+/// session.wait_for(&wait).unwrap();
+/// assert_eq!(session.buffer(), "foo --help ");
+/// session.send_key(Key::Ctrl('c')).unwrap();
+/// ```
+pub struct Session {
+    process: PtyProcess,
+    input: std::fs::File,
+    events: Receiver<Event>,
+    parser: Arc<Mutex<vt100::Parser<Terminal>>>,
+    shutdown: Arc<AtomicBool>,
+    reader: Option<std::thread::JoinHandle<()>>,
+}
+
+impl Session {
+    /// Start `command` and wait for it to be ready for input
+    pub(crate) fn spawn(
+        command: Command,
+        echo: bool,
+        term: &Term,
+        deadline: Instant,
+    ) -> Result<Self, Error> {
+        let shell = command.get_program().to_string_lossy().into_owned();
+        let mut process = PtyProcess::spawn(command).map_err(|err| match err {
+            ptyprocess::errno::Errno::ENOENT => Error::ShellNotFound(shell),
+            err => Error::Io(err.into()),
+        })?;
+        process
+            .set_window_size(term.get_width(), term.get_height())
+            .map_err(std::io::Error::from)?;
+        // for some reason bash does not produce anything with echo disabled...
+        process.set_echo(echo, None).map_err(std::io::Error::from)?;
+
+        let parser = Arc::new(Mutex::new(vt100::Parser::new_with_callbacks(
+            term.get_height(),
+            term.get_width(),
+            0,
+            Terminal::default(),
+        )));
+        let shutdown = Arc::new(AtomicBool::new(false));
+
+        let output = process.get_raw_handle().map_err(std::io::Error::from)?;
+        let input = process.get_raw_handle().map_err(std::io::Error::from)?;
+        let (snd, events) = std::sync::mpsc::channel();
+        let reader = {
+            let parser = parser.clone();
+            let shutdown = shutdown.clone();
+            std::thread::spawn(move || read(output, &parser, &snd, &shutdown))
+        };
+
+        let mut session = Self {
+            process,
+            input,
+            events,
+            parser,
+            shutdown,
+            reader: Some(reader),
+        };
+
+        // Wait for the prompt's sentinel before typing so shell startup (e.g. `compinit`) doesn't
+        // count against the `WaitStrategy`, no matter what the rc files print or how long they
+        // take.  If the shell never reports it, fallback to waiting for the startup output to go
+        // quiet.
+        let mut event = crate::wait::recv_until(&session.events, deadline);
+        loop {
+            match event {
+                Ok(Event::Ready) => {
+                    session.settle(READY_SETTLE, deadline)?;
+                    break;
+                }
+                Ok(Event::Output | Event::Done) => {}
+                Err(Interrupted::Elapsed) if Instant::now() < deadline => break,
+                Err(interrupted) => return Err(session.interrupted(interrupted)),
+            }
+            let quiet = deadline.min(Instant::now() + READY_FALLBACK);
+            event = crate::wait::recv_until(&session.events, quiet);
+        }
+
+        Ok(session)
+    }
+
+    /// Wait for the output to go quiet for `period`, giving up at `deadline`
+    fn settle(&mut self, period: Duration, deadline: Instant) -> Result<(), Error> {
+        loop {
+            let quiet = deadline.min(Instant::now() + period);
+            match crate::wait::recv_until(&self.events, quiet) {
+                Ok(_) => {}
+                Err(Interrupted::Elapsed) => return Ok(()),
+                Err(interrupted) => return Err(self.interrupted(interrupted)),
+            }
+        }
+    }
+
+    /// Type `text` into the shell
+    pub fn send(&mut self, text: &str) -> Result<(), Error> {
+        self.input.write_all(text.as_bytes())?;
+        self.input.flush()?;
+        Ok(())
+    }
+
+    /// Press `key` in the shell
+    pub fn send_key(&mut self, key: Key) -> Result<(), Error> {
+        let application_cursor = self.lock().screen().application_cursor();
+        self.input.write_all(&encode(key, application_cursor)?)?;
+        self.input.flush()?;
+        Ok(())
+    }
+
+    /// Type each step of `input` into the shell
+    ///
+    /// The cursor is moved with `Ctrl-A` and `Right`, assuming the command-line holds only the text
+    /// typed by `input`.
+    pub fn send_input(&mut self, input: &Input) -> Result<(), Error> {
+        // Track the command-line to translate cursor offsets into key presses
+        let mut line = String::new();
+        let mut cursor = 0;
+        for event in input.get_events() {
+            match event {
+                InputEvent::Text(text) => {
+                    self.send(text)?;
+                    line.insert_str(cursor, text);
+                    cursor += text.len();
+                }
+                InputEvent::Key(key) => self.send_key(*key)?,
+                InputEvent::Cursor(offset) => {
+                    cursor = floor_char_boundary(&line, *offset);
+                    self.send_key(Key::Ctrl('a'))?;
+                    for _ in line[..cursor].chars() {
+                        self.send_key(Key::Right)?;
+                    }
+                }
+                _ => {
+                    return Err(Error::Unsupported(format!("{event:?}")));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Block until the shell is done according to `wait`
+    pub fn wait_for(&mut self, wait: &WaitStrategy) -> Result<(), Error> {
+        self.wait_until(wait, Instant::now() + wait.get_timeout())
+    }
+
+    pub(crate) fn wait_until(
+        &mut self,
+        wait: &WaitStrategy,
+        deadline: Instant,
+    ) -> Result<(), Error> {
+        wait.wait(&self.events, &self.parser, deadline)
+            .map_err(|interrupted| self.interrupted(interrupted))
+    }
+
+    /// What the shell currently shows
+    pub fn screen(&self) -> Screen {
+        self.lock().screen().clone()
+    }
+
+    /// Command-line being edited
+    pub fn buffer(&self) -> String {
+        crate::parse::buffer(self.lock().screen())
+    }
+
+    /// Byte offset of the cursor in the [buffer][Session::buffer]
+    pub fn cursor(&self) -> usize {
+        crate::parse::cursor(self.lock().screen())
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, vt100::Parser<Terminal>> {
+        self.parser.lock().unwrap_or_else(|err| err.into_inner())
+    }
+
+    fn interrupted(&mut self, interrupted: Interrupted) -> Error {
+        let screen = self.screen().contents();
+        match interrupted {
+            Interrupted::Elapsed => Error::Timeout { screen },
+            Interrupted::Closed => {
+                if let Some(reader) = self.reader.take() {
+                    let _ = reader.join();
+                }
+                let code = match self.process.wait() {
+                    Ok(ptyprocess::WaitStatus::Exited(_, code)) => Some(code),
+                    _ => None,
+                };
+                Error::ShellExited { code, screen }
+            }
+        }
+    }
+}
+
+impl std::fmt::Debug for Session {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Session")
+            .field("pid", &self.process.pid())
+            .finish_non_exhaustive()
+    }
+}
+
+impl Drop for Session {
+    fn drop(&mut self) {
+        self.shutdown.store(true, Ordering::SeqCst);
+        // The shell is a session leader, so this also stops anything a completion script left
+        // running on the pty.
+        let _ = nix::sys::signal::killpg(self.process.pid(), nix::sys::signal::Signal::SIGKILL);
+        if let Some(reader) = self.reader.take() {
+            let _ = reader.join();
+        }
+        let _ = self.process.wait();
+    }
+}
+
+pub(crate) fn floor_char_boundary(line: &str, offset: usize) -> usize {
+    let mut offset = offset.min(line.len());
+    while !line.is_char_boundary(offset) {
+        offset -= 1;
+    }
+    offset
+}
+
+/// Bytes xterm sends for `key`
+///
+/// Every runtime runs its shell with `TERM=xterm`.  Cursor keys change when the shell switches the
+/// terminal into application cursor mode.
+fn encode(key: Key, application_cursor: bool) -> Result<Vec<u8>, Error> {
+    let cursor = |code: u8| {
+        let prefix = if application_cursor {
+            b"\x1bO"
+        } else {
+            b"\x1b["
+        };
+        let mut bytes = prefix.to_vec();
+        bytes.push(code);
+        bytes
+    };
+    let bytes = match key {
+        Key::Tab => b"\t".to_vec(),
+        Key::ShiftTab => b"\x1b[Z".to_vec(),
+        Key::Enter => b"\r".to_vec(),
+        Key::Escape => b"\x1b".to_vec(),
+        Key::Backspace => b"\x7f".to_vec(),
+        Key::Up => cursor(b'A'),
+        Key::Down => cursor(b'B'),
+        Key::Right => cursor(b'C'),
+        Key::Left => cursor(b'D'),
+        Key::Home => cursor(b'H'),
+        Key::End => cursor(b'F'),
+        Key::Ctrl(c @ ('a'..='z' | 'A'..='Z' | '@' | '[' | '\\' | ']' | '^' | '_')) => {
+            vec![(c.to_ascii_uppercase() as u8) & 0x1f]
+        }
+        _ => return Err(Error::Unsupported(format!("{key:?}"))),
+    };
+    Ok(bytes)
+}
+
+/// Terminal behavior beyond what `vt100` renders
+#[derive(Default)]
+pub(crate) struct Terminal {
+    /// Times the ready sentinel was printed
+    ready: usize,
+    /// Times the done sentinel was printed
+    done: usize,
+    /// Responses to queries, to be written back to the shell
+    replies: Vec<u8>,
+}
+
+impl vt100::Callbacks for Terminal {
+    fn unhandled_csi(
+        &mut self,
+        screen: &mut Screen,
+        i1: Option<u8>,
+        _: Option<u8>,
+        params: &[&[u16]],
+        c: char,
+    ) {
+        // Line editors like reedline and PSReadLine ask where the cursor is and stall without an
+        // answer
+        if let (None, [[6]], 'n') = (i1, params, c) {
+            let (row, col) = screen.cursor_position();
+            let reply = format!("\x1b[{};{}R", row + 1, col + 1);
+            self.replies.extend_from_slice(reply.as_bytes());
+        }
+    }
+
+    fn unhandled_osc(&mut self, _: &mut Screen, params: &[&[u8]]) {
+        match params {
+            [SENTINEL_CODE, READY_SENTINEL_PAYLOAD] => self.ready += 1,
+            [SENTINEL_CODE, DONE_SENTINEL_PAYLOAD] => self.done += 1,
+            _ => {}
+        }
+    }
+}
+
+pub(crate) enum Event {
+    Output,
+    Ready,
+    Done,
+}
+
+fn read(
+    mut stream: std::fs::File,
+    parser: &Mutex<vt100::Parser<Terminal>>,
+    events: &Sender<Event>,
+    shutdown: &AtomicBool,
+) {
+    let mut buf = [0; 2048];
+    loop {
+        // Poll rather than block so we don't depend on the pty closing, as a completion script may
+        // leave processes behind that hold it open
+        let mut fds = [nix::poll::PollFd::new(
+            stream.as_raw_fd(),
+            nix::poll::PollFlags::POLLIN,
+        )];
+        let readable = nix::poll::poll(&mut fds, POLL_INTERVAL_MS)
+            .map(|n| 0 < n)
+            .unwrap_or(false);
+        if shutdown.load(Ordering::SeqCst) {
+            // fish clears completions on process teardown
+            break;
+        }
+        if !readable {
+            continue;
+        }
+        let n = match stream.read(&mut buf) {
+            Ok(n) => n,
+            Err(err) if err.kind() == std::io::ErrorKind::Interrupted => continue,
+            // The pty reports an error once the shell closes it
+            Err(_) => 0,
+        };
+        let buf = &buf[..n];
+        if buf.is_empty() {
+            break;
+        }
+        let (ready, done) = {
+            let mut parser = parser.lock().unwrap_or_else(|err| err.into_inner());
+            let Terminal { ready, done, .. } = *parser.callbacks();
+            parser.process(buf);
+            let replies = std::mem::take(&mut parser.callbacks_mut().replies);
+            if !replies.is_empty() {
+                let _ = stream.write_all(&replies);
+            }
+            (
+                ready < parser.callbacks().ready,
+                done < parser.callbacks().done,
+            )
+        };
+        if done {
+            let _ = events.send(Event::Done);
+        }
+        if ready {
+            let _ = events.send(Event::Ready);
+        }
+        if !ready && !done {
+            let _ = events.send(Event::Output);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn encode_keys() {
+        let cases: &[(Key, &[u8], &[u8])] = &[
+            (Key::Tab, b"\t", b"\t"),
+            (Key::ShiftTab, b"\x1b[Z", b"\x1b[Z"),
+            (Key::Enter, b"\r", b"\r"),
+            (Key::Escape, b"\x1b", b"\x1b"),
+            (Key::Backspace, b"\x7f", b"\x7f"),
+            (Key::Up, b"\x1b[A", b"\x1bOA"),
+            (Key::Down, b"\x1b[B", b"\x1bOB"),
+            (Key::Right, b"\x1b[C", b"\x1bOC"),
+            (Key::Left, b"\x1b[D", b"\x1bOD"),
+            (Key::Home, b"\x1b[H", b"\x1bOH"),
+            (Key::End, b"\x1b[F", b"\x1bOF"),
+            (Key::Ctrl('a'), b"\x01", b"\x01"),
+            (Key::Ctrl('C'), b"\x03", b"\x03"),
+            (Key::Ctrl('z'), b"\x1a", b"\x1a"),
+            (Key::Ctrl('@'), b"\x00", b"\x00"),
+            (Key::Ctrl('['), b"\x1b", b"\x1b"),
+            (Key::Ctrl('_'), b"\x1f", b"\x1f"),
+        ];
+        for (key, normal, application) in cases {
+            assert_eq!(encode(*key, false).unwrap(), *normal, "{key:?}");
+            assert_eq!(encode(*key, true).unwrap(), *application, "{key:?}");
+        }
+    }
+
+    #[test]
+    fn encode_unsupported_ctrl() {
+        for c in ['1', ' ', 'é'] {
+            assert!(matches!(
+                encode(Key::Ctrl(c), false),
+                Err(Error::Unsupported(_))
+            ));
+        }
+    }
+}
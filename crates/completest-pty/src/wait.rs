@@ -0,0 +1,437 @@
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::sync::mpsc::Receiver;
+use std::sync::mpsc::RecvTimeoutError;
+use std::sync::mpsc::TryRecvError;
+use std::time::Duration;
+use std::time::Instant;
+
+use crate::Screen;
+use crate::session::Event;
+use crate::session::Terminal;
+
+/// How to decide a shell is done completing
+///
+/// After typing the input, we wait for the screen to match this strategy before capturing it.
+/// Runtimes whose shell can report when it has finished completing default to
+/// [`WaitStrategy::marker`].  Other shells don't, so they fallback to [`WaitStrategy::quiet`].
+///
+/// Regardless of the strategy, the shell is killed and [`Error::Timeout`][crate::Error::Timeout]
+/// is returned if it takes longer than [`WaitStrategy::timeout`].
+///
+/// # Example
+///
+/// ```rust,no_run
+/// # use std::time::Duration;
+/// use completest_pty::WaitStrategy;
+///
+/// // `compinit` can be slow on the first run
+/// let slow = WaitStrategy::quiet(Duration::from_millis(500)).timeout(Duration::from_secs(60));
+/// let prompt = WaitStrategy::regex(r"(?m)^% ").unwrap();
+/// let custom = WaitStrategy::predicate(|screen| screen.contents().contains("--help"));
+/// ```
+#[derive(Clone)]
+pub struct WaitStrategy {
+    kind: WaitKind,
+    timeout: Duration,
+}
+
+#[derive(Clone)]
+enum WaitKind {
+    Quiet(Duration),
+    Marker {
+        period: Duration,
+        fallback: Duration,
+    },
+    Stable {
+        interval: Duration,
+        polls: usize,
+    },
+    Regex(regex::Regex),
+    Deadline(Duration),
+    Predicate(Arc<dyn Fn(&Screen) -> bool + Send + Sync>),
+}
+
+impl WaitStrategy {
+    const DEFAULT_TIMEOUT: Duration = Duration::from_secs(10);
+
+    /// Wait for some output, then until nothing has been written for `period`
+    pub fn quiet(period: Duration) -> Self {
+        Self::from_kind(WaitKind::Quiet(period))
+    }
+
+    /// Wait for the shell to report it is done completing, then until nothing has been written
+    /// for `period`
+    ///
+    /// The shell reports it before drawing the candidates, so `period` only needs to cover
+    /// rendering.  Drawing a new prompt, like after `Enter`, counts as done too.
+    ///
+    /// If the shell doesn't report it, like for input that never presses `Tab` or a home from
+    /// before runtimes reported it, this falls back to waiting until nothing has been written for
+    /// `fallback`.
+    pub fn marker(period: Duration, fallback: Duration) -> Self {
+        Self::from_kind(WaitKind::Marker { period, fallback })
+    }
+
+    /// Wait for some output, then poll the screen every `interval` until it is unchanged for
+    /// `polls` polls in a row
+    pub fn stable(interval: Duration, polls: usize) -> Self {
+        Self::from_kind(WaitKind::Stable { interval, polls })
+    }
+
+    /// Wait until the screen's contents match `pattern`
+    pub fn regex(pattern: &str) -> Result<Self, regex::Error> {
+        let regex = regex::Regex::new(pattern)?;
+        Ok(Self::from_kind(WaitKind::Regex(regex)))
+    }
+
+    /// Capture the screen after exactly `duration`, regardless of the output
+    pub fn deadline(duration: Duration) -> Self {
+        Self::from_kind(WaitKind::Deadline(duration))
+    }
+
+    /// Wait until `predicate` returns `true` for the screen
+    pub fn predicate(predicate: impl Fn(&Screen) -> bool + Send + Sync + 'static) -> Self {
+        Self::from_kind(WaitKind::Predicate(Arc::new(predicate)))
+    }
+
+    /// Give up on a shell that takes longer than `timeout`, including its startup
+    ///
+    /// Defaults to 10 seconds.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// How long to wait on a shell before giving up
+    pub fn get_timeout(&self) -> Duration {
+        self.timeout
+    }
+
+    fn from_kind(kind: WaitKind) -> Self {
+        Self {
+            kind,
+            timeout: Self::DEFAULT_TIMEOUT,
+        }
+    }
+
+    /// Block until the shell is done according to this strategy
+    pub(crate) fn wait(
+        &self,
+        events: &Receiver<Event>,
+        parser: &Mutex<vt100::Parser<Terminal>>,
+        deadline: Instant,
+    ) -> Result<(), Interrupted> {
+        match &self.kind {
+            WaitKind::Quiet(period) => {
+                recv_until(events, deadline)?;
+                quiet(events, *period, deadline)
+            }
+            WaitKind::Marker { period, fallback } => {
+                let mut event = recv_until(events, deadline)?;
+                loop {
+                    if matches!(event, Event::Done | Event::Ready) {
+                        return quiet(events, *period, deadline);
+                    }
+                    let remaining = remaining(deadline)?;
+                    event = match events.recv_timeout((*fallback).min(remaining)) {
+                        Ok(event) => event,
+                        Err(RecvTimeoutError::Disconnected) => return Err(Interrupted::Closed),
+                        Err(RecvTimeoutError::Timeout) if *fallback <= remaining => return Ok(()),
+                        Err(RecvTimeoutError::Timeout) => return Err(Interrupted::Elapsed),
+                    };
+                }
+            }
+            WaitKind::Stable { interval, polls } => {
+                recv_until(events, deadline)?;
+                let mut previous = contents(parser);
+                let mut unchanged = 0;
+                while unchanged < *polls {
+                    std::thread::sleep((*interval).min(remaining(deadline)?));
+                    loop {
+                        match events.try_recv() {
+                            Ok(_) => {}
+                            Err(TryRecvError::Empty) => break,
+                            Err(TryRecvError::Disconnected) => return Err(Interrupted::Closed),
+                        }
+                    }
+                    let current = contents(parser);
+                    if current == previous {
+                        unchanged += 1;
+                    } else {
+                        unchanged = 0;
+                        previous = current;
+                    }
+                }
+                Ok(())
+            }
+            WaitKind::Regex(regex) => wait_for(events, parser, deadline, |screen| {
+                regex.is_match(&screen.contents())
+            }),
+            WaitKind::Deadline(duration) => {
+                let capture = Instant::now() + *duration;
+                loop {
+                    match recv_until(events, capture.min(deadline)) {
+                        Ok(_) => {}
+                        Err(Interrupted::Elapsed) if capture <= deadline => return Ok(()),
+                        Err(interrupted) => return Err(interrupted),
+                    }
+                }
+            }
+            WaitKind::Predicate(predicate) => {
+                wait_for(events, parser, deadline, |screen| predicate(screen))
+            }
+        }
+    }
+}
+
+impl std::fmt::Debug for WaitStrategy {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut f = match &self.kind {
+            WaitKind::Quiet(period) => {
+                let mut f = f.debug_struct("Quiet");
+                f.field("period", period);
+                f
+            }
+            WaitKind::Marker { period, fallback } => {
+                let mut f = f.debug_struct("Marker");
+                f.field("period", period).field("fallback", fallback);
+                f
+            }
+            WaitKind::Stable { interval, polls } => {
+                let mut f = f.debug_struct("Stable");
+                f.field("interval", interval).field("polls", polls);
+                f
+            }
+            WaitKind::Regex(regex) => {
+                let mut f = f.debug_struct("Regex");
+                f.field("pattern", &regex.as_str());
+                f
+            }
+            WaitKind::Deadline(duration) => {
+                let mut f = f.debug_struct("Deadline");
+                f.field("duration", duration);
+                f
+            }
+            WaitKind::Predicate(_) => f.debug_struct("Predicate"),
+        };
+        f.field("timeout", &self.timeout).finish()
+    }
+}
+
+/// Why the shell stopped before it was done
+#[derive(Debug)]
+pub(crate) enum Interrupted {
+    /// The overall deadline passed
+    Elapsed,
+    /// The shell closed the pty
+    Closed,
+}
+
+/// Wait for the next event
+pub(crate) fn recv_until(
+    events: &Receiver<Event>,
+    deadline: Instant,
+) -> Result<Event, Interrupted> {
+    match events.recv_timeout(remaining(deadline)?) {
+        Ok(event) => Ok(event),
+        Err(RecvTimeoutError::Disconnected) => Err(Interrupted::Closed),
+        Err(RecvTimeoutError::Timeout) => Err(Interrupted::Elapsed),
+    }
+}
+
+/// Wait until nothing has been written for `period`
+fn quiet(events: &Receiver<Event>, period: Duration, deadline: Instant) -> Result<(), Interrupted> {
+    loop {
+        let remaining = remaining(deadline)?;
+        match events.recv_timeout(period.min(remaining)) {
+            Ok(_) => {}
+            Err(RecvTimeoutError::Disconnected) => return Err(Interrupted::Closed),
+            Err(RecvTimeoutError::Timeout) if period <= remaining => return Ok(()),
+            Err(RecvTimeoutError::Timeout) => return Err(Interrupted::Elapsed),
+        }
+    }
+}
+
+fn remaining(deadline: Instant) -> Result<Duration, Interrupted> {
+    let remaining = deadline.saturating_duration_since(Instant::now());
+    if remaining.is_zero() {
+        Err(Interrupted::Elapsed)
+    } else {
+        Ok(remaining)
+    }
+}
+
+fn wait_for(
+    events: &Receiver<Event>,
+    parser: &Mutex<vt100::Parser<Terminal>>,
+    deadline: Instant,
+    done: impl Fn(&Screen) -> bool,
+) -> Result<(), Interrupted> {
+    loop {
+        recv_until(events, deadline)?;
+        let parser = parser.lock().unwrap_or_else(|err| err.into_inner());
+        if done(parser.screen()) {
+            return Ok(());
+        }
+    }
+}
+
+fn contents(parser: &Mutex<vt100::Parser<Terminal>>) -> Vec<u8> {
+    let parser = parser.lock().unwrap_or_else(|err| err.into_inner());
+    parser.screen().contents_formatted()
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::mpsc::Sender;
+
+    use super::*;
+
+    const MS: Duration = Duration::from_millis(1);
+
+    /// Send each event after its delay, keeping the channel open afterwards
+    fn script(
+        events: &Sender<Event>,
+        parser: &Arc<Mutex<vt100::Parser<Terminal>>>,
+        steps: Vec<(u64, Event, &'static [u8])>,
+    ) {
+        let events = events.clone();
+        let parser = parser.clone();
+        std::thread::spawn(move || {
+            for (delay, event, bytes) in steps {
+                std::thread::sleep(Duration::from_millis(delay));
+                parser
+                    .lock()
+                    .unwrap_or_else(|err| err.into_inner())
+                    .process(bytes);
+                let _ = events.send(event);
+            }
+        });
+    }
+
+    /// Wait on the events of `steps`, returning the outcome and how long it took
+    fn wait(
+        strategy: &WaitStrategy,
+        timeout: u64,
+        steps: Vec<(u64, Event, &'static [u8])>,
+    ) -> (Result<(), Interrupted>, Duration) {
+        let (sender, events) = std::sync::mpsc::channel();
+        let parser = Arc::new(Mutex::new(vt100::Parser::new_with_callbacks(
+            24,
+            80,
+            0,
+            Terminal::default(),
+        )));
+        script(&sender, &parser, steps);
+        let start = Instant::now();
+        let result = strategy.wait(&events, &parser, start + Duration::from_millis(timeout));
+        (result, start.elapsed())
+    }
+
+    #[test]
+    fn quiet() {
+        let strategy = WaitStrategy::quiet(50 * MS);
+        let (result, elapsed) = wait(
+            &strategy,
+            1000,
+            vec![
+                (0, Event::Output, b"a"),
+                (20, Event::Output, b"b"),
+                (20, Event::Output, b"c"),
+            ],
+        );
+        assert!(result.is_ok());
+        assert!(elapsed >= 90 * MS, "{elapsed:?}");
+        assert!(elapsed < 500 * MS, "{elapsed:?}");
+
+        let (result, elapsed) = wait(&strategy, 100, vec![]);
+        assert!(matches!(result, Err(Interrupted::Elapsed)));
+        assert!(elapsed >= 100 * MS, "{elapsed:?}");
+    }
+
+    #[test]
+    fn quiet_closed() {
+        let strategy = WaitStrategy::quiet(50 * MS);
+        let (sender, events) = std::sync::mpsc::channel();
+        let parser = Mutex::new(vt100::Parser::new_with_callbacks(
+            24,
+            80,
+            0,
+            Terminal::default(),
+        ));
+        sender.send(Event::Output).unwrap();
+        drop(sender);
+        let result = strategy.wait(&events, &parser, Instant::now() + 1000 * MS);
+        assert!(matches!(result, Err(Interrupted::Closed)));
+    }
+
+    #[test]
+    fn deadline() {
+        let strategy = WaitStrategy::deadline(100 * MS);
+        let steps = || {
+            (0..20)
+                .map(|_| (10, Event::Output, b"a" as &[u8]))
+                .collect::<Vec<_>>()
+        };
+        let (result, elapsed) = wait(&strategy, 1000, steps());
+        assert!(result.is_ok());
+        assert!(elapsed >= 100 * MS, "{elapsed:?}");
+        assert!(elapsed < 500 * MS, "{elapsed:?}");
+
+        let (result, elapsed) = wait(&strategy, 1000, vec![]);
+        assert!(result.is_ok());
+        assert!(elapsed >= 100 * MS, "{elapsed:?}");
+
+        let (result, _) = wait(&strategy, 50, steps());
+        assert!(matches!(result, Err(Interrupted::Elapsed)));
+    }
+
+    #[test]
+    fn stable() {
+        let strategy = WaitStrategy::stable(20 * MS, 3);
+        let (result, elapsed) = wait(
+            &strategy,
+            1000,
+            vec![
+                (0, Event::Output, b"a"),
+                (30, Event::Output, b"b"),
+                (30, Event::Output, b"c"),
+            ],
+        );
+        assert!(result.is_ok());
+        assert!(elapsed >= 120 * MS, "{elapsed:?}");
+        assert!(elapsed < 500 * MS, "{elapsed:?}");
+
+        let (result, _) = wait(&strategy, 100, vec![]);
+        assert!(matches!(result, Err(Interrupted::Elapsed)));
+    }
+
+    #[test]
+    fn marker() {
+        let strategy = WaitStrategy::marker(20 * MS, 300 * MS);
+        let (result, elapsed) = wait(
+            &strategy,
+            1000,
+            vec![
+                (0, Event::Output, b"a"),
+                (50, Event::Done, b""),
+                (10, Event::Output, b"b"),
+            ],
+        );
+        assert!(result.is_ok());
+        assert!(elapsed >= 80 * MS, "{elapsed:?}");
+        assert!(elapsed < 300 * MS, "{elapsed:?}");
+
+        let (result, elapsed) = wait(&strategy, 1000, vec![(0, Event::Ready, b"")]);
+        assert!(result.is_ok());
+        assert!(elapsed < 300 * MS, "{elapsed:?}");
+
+        let (result, elapsed) = wait(&strategy, 1000, vec![(0, Event::Output, b"a")]);
+        assert!(result.is_ok());
+        assert!(elapsed >= 300 * MS, "{elapsed:?}");
+
+        let (result, _) = wait(&strategy, 200, vec![(0, Event::Output, b"a")]);
+        assert!(matches!(result, Err(Interrupted::Elapsed)));
+    }
+}
@@ -53,6 +53,436 @@ impl Default for Term {
     }
 }
 
+/// What to type into a shell
+///
+/// Strings convert into an `Input`, treating `\t` as [`Key::Tab`].
+///
+/// # Example
+///
+/// ```rust
+/// use completest::Input;
+/// use completest::Key;
+///
+/// let input = Input::new().text("foo --co").key(Key::Tab).key(Key::Tab);
+/// assert_eq!(input, Input::from("foo --co\t\t"));
+///
+/// // Complete `--co` in the middle of the command-line
+/// let input = Input::new().text("foo --co bar").cursor(8).key(Key::Tab);
+/// ```
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Input {
+    events: Vec<InputEvent>,
+}
+
+#[allow(missing_docs)]
+impl Input {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Type `text`
+    pub fn text(mut self, text: impl AsRef<str>) -> Self {
+        let text = text.as_ref();
+        if text.is_empty() {
+            return self;
+        }
+        if let Some(InputEvent::Text(last)) = self.events.last_mut() {
+            last.push_str(text);
+        } else {
+            self.events.push(InputEvent::Text(text.to_owned()));
+        }
+        self
+    }
+
+    /// Press `key`
+    pub fn key(mut self, key: Key) -> Self {
+        self.events.push(InputEvent::Key(key));
+        self
+    }
+
+    /// Move the cursor to byte `offset` of the command-line
+    ///
+    /// Text typed afterwards is inserted at the cursor.
+    pub fn cursor(mut self, offset: usize) -> Self {
+        self.events.push(InputEvent::Cursor(offset));
+        self
+    }
+
+    pub fn get_events(&self) -> &[InputEvent] {
+        &self.events
+    }
+}
+
+impl From<&str> for Input {
+    fn from(input: &str) -> Self {
+        let mut parts = input.split('\t');
+        let mut result = Self::new().text(parts.next().unwrap_or_default());
+        for part in parts {
+            result = result.key(Key::Tab).text(part);
+        }
+        result
+    }
+}
+
+impl From<String> for Input {
+    fn from(input: String) -> Self {
+        Self::from(input.as_str())
+    }
+}
+
+/// A step in typing an [`Input`]
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum InputEvent {
+    /// Literal text
+    Text(String),
+    /// A key that isn't text
+    Key(Key),
+    /// Move the cursor to a byte offset of the command-line
+    Cursor(usize),
+}
+
+/// Key to press in an [`Input`]
+///
+/// Runtimes translate these for their shell, like into escape sequences for a terminal.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+#[allow(missing_docs)]
+pub enum Key {
+    Tab,
+    ShiftTab,
+    Enter,
+    Escape,
+    Backspace,
+    Up,
+    Down,
+    Left,
+    Right,
+    Home,
+    End,
+    /// `Ctrl` with an ASCII letter, like `Ctrl('c')`
+    Ctrl(char),
+}
+
+/// Completions offered by a shell for an input
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Completion {
+    candidates: Vec<Candidate>,
+    highlighted: Option<String>,
+    buffer: String,
+    cursor: usize,
+    screen: String,
+}
+
+#[allow(missing_docs)]
+impl Completion {
+    pub fn new(screen: impl Into<String>) -> Self {
+        Self {
+            candidates: Vec::new(),
+            highlighted: None,
+            buffer: String::new(),
+            cursor: 0,
+            screen: screen.into(),
+        }
+    }
+
+    pub fn candidate(mut self, candidate: Candidate) -> Self {
+        self.candidates.push(candidate);
+        self
+    }
+
+    pub fn candidates(mut self, candidates: impl IntoIterator<Item = Candidate>) -> Self {
+        self.candidates.extend(candidates);
+        self
+    }
+
+    /// The value selected in the shell's completion menu
+    pub fn highlighted(mut self, value: impl Into<String>) -> Self {
+        self.highlighted = Some(value.into());
+        self
+    }
+
+    /// The command-line after completing
+    pub fn buffer(mut self, buffer: impl Into<String>) -> Self {
+        self.buffer = buffer.into();
+        self
+    }
+
+    /// Byte offset of the cursor in the [buffer][Completion::buffer]
+    pub fn cursor(mut self, cursor: usize) -> Self {
+        self.cursor = cursor;
+        self
+    }
+
+    pub fn get_candidates(&self) -> &[Candidate] {
+        &self.candidates
+    }
+
+    pub fn get_highlighted(&self) -> Option<&str> {
+        self.highlighted.as_deref()
+    }
+
+    pub fn get_buffer(&self) -> &str {
+        &self.buffer
+    }
+
+    pub fn get_cursor(&self) -> usize {
+        self.cursor
+    }
+
+    /// Raw output the candidates were recovered from, usable for snapshots
+    ///
+    /// This can differ from [`Runtime::complete`] when the shell needs to draw more to report the
+    /// [buffer][Completion::get_buffer], like bash echoing the command-line when listing
+    /// candidates.
+    pub fn get_screen(&self) -> &str {
+        &self.screen
+    }
+}
+
+/// A value offered by a shell in a [`Completion`]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Candidate {
+    value: String,
+    description: Option<String>,
+    group: Option<String>,
+    display: Option<String>,
+}
+
+#[allow(missing_docs)]
+impl Candidate {
+    pub fn new(value: impl Into<String>) -> Self {
+        Self {
+            value: value.into(),
+            description: None,
+            group: None,
+            display: None,
+        }
+    }
+
+    pub fn description(mut self, description: impl Into<String>) -> Self {
+        self.description = Some(description.into());
+        self
+    }
+
+    /// The group or tag the shell listed the candidate under
+    pub fn group(mut self, group: impl Into<String>) -> Self {
+        self.group = Some(group.into());
+        self
+    }
+
+    /// How the shell lists the candidate, when it differs from the value
+    pub fn display(mut self, display: impl Into<String>) -> Self {
+        self.display = Some(display.into());
+        self
+    }
+
+    pub fn get_value(&self) -> &str {
+        &self.value
+    }
+
+    pub fn get_description(&self) -> Option<&str> {
+        self.description.as_deref()
+    }
+
+    pub fn get_group(&self) -> Option<&str> {
+        self.group.as_deref()
+    }
+
+    pub fn get_display(&self) -> Option<&str> {
+        self.display.as_deref()
+    }
+}
+
+/// Failure running completions
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum Error {
+    /// The shell is not installed
+    ShellNotFound(String),
+    /// The shell did not finish completing in time
+    Timeout {
+        /// What the shell rendered before timing out
+        screen: String,
+    },
+    /// The shell exited before it finished completing
+    ShellExited {
+        /// Exit code, if the shell wasn't killed by a signal
+        code: Option<i32>,
+        /// What the shell rendered before exiting
+        screen: String,
+    },
+    /// The completion script could not be parsed or loaded
+    Script(String),
+    /// The runtime can't do what was asked, like pressing a key the shell has no equivalent for
+    Unsupported(String),
+    /// Failure interacting with the shell or its home
+    Io(std::io::Error),
+}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::ShellNotFound(shell) => write!(f, "`{shell}` is not installed"),
+            Self::Timeout { .. } => "shell did not finish completing in time".fmt(f),
+            Self::ShellExited {
+                code: Some(code), ..
+            } => write!(f, "shell exited early with code {code}"),
+            Self::ShellExited { code: None, .. } => "shell was killed early".fmt(f),
+            Self::Script(err) => write!(f, "completion script failed to load: {err}"),
+            Self::Unsupported(what) => write!(f, "unsupported by the runtime: {what}"),
+            Self::Io(err) => err.fmt(f),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Io(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<std::io::Error> for Error {
+    fn from(err: std::io::Error) -> Self {
+        Self::Io(err)
+    }
+}
+
+/// Why a runtime can't run on this system, like its shell not being installed
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Unavailable {
+    reason: String,
+}
+
+#[allow(missing_docs)]
+impl Unavailable {
+    pub fn new(reason: impl Into<String>) -> Self {
+        Self {
+            reason: reason.into(),
+        }
+    }
+
+    pub fn get_reason(&self) -> &str {
+        &self.reason
+    }
+}
+
+impl std::fmt::Display for Unavailable {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.reason.fmt(f)
+    }
+}
+
+impl std::error::Error for Unavailable {}
+
+/// Version of the shell a runtime runs, as the shell reports it
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Version {
+    version: String,
+}
+
+#[allow(missing_docs)]
+impl Version {
+    pub fn new(version: impl Into<String>) -> Self {
+        Self {
+            version: version.into(),
+        }
+    }
+
+    pub fn get_version(&self) -> &str {
+        &self.version
+    }
+
+    /// Dot-separated numbers, starting from the first digit
+    ///
+    /// For example, bash's `5.2.15(1)-release` is `[5, 2, 15]`.
+    pub fn get_numbers(&self) -> Vec<u64> {
+        let Some(start) = self.version.find(|c: char| c.is_ascii_digit()) else {
+            return Vec::new();
+        };
+        let mut numbers = Vec::new();
+        for part in self.version[start..].split('.') {
+            let digits = part.len() - part.trim_start_matches(|c: char| c.is_ascii_digit()).len();
+            let Ok(number) = part[..digits].parse() else {
+                break;
+            };
+            numbers.push(number);
+            if digits < part.len() {
+                break;
+            }
+        }
+        numbers
+    }
+}
+
+impl std::fmt::Display for Version {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.version.fmt(f)
+    }
+}
+
+/// Expectation file to compare against for a shell's [`Version`]
+///
+/// Prefers the most specific file that exists, with the version before the first extension, like
+/// `complete@5.2.15.term.svg`, then `complete@5.2.term.svg`, then `complete@5.term.svg`, falling
+/// back to `path` itself.
+pub fn expected_path(path: impl AsRef<std::path::Path>, version: &Version) -> std::path::PathBuf {
+    let path = path.as_ref();
+    let Some(name) = path.file_name().and_then(|name| name.to_str()) else {
+        return path.to_owned();
+    };
+    let (stem, extension) = match name.find('.') {
+        Some(dot) => name.split_at(dot),
+        None => (name, ""),
+    };
+    let numbers = version.get_numbers();
+    for len in (1..=numbers.len()).rev() {
+        let version = numbers[..len]
+            .iter()
+            .map(|number| number.to_string())
+            .collect::<Vec<_>>()
+            .join(".");
+        let candidate = path.with_file_name(format!("{stem}@{version}{extension}"));
+        if candidate.exists() {
+            return candidate;
+        }
+    }
+    path.to_owned()
+}
+
+/// Skip the rest of a test when a runtime is [unavailable][RuntimeBuilder::is_available]
+///
+/// The reason is reported on `stderr`.  By default, this returns from the test; pass what to do
+/// instead as the second argument, like `continue` when looping over runtimes.
+///
+/// ```rust
+/// # #[derive(Debug)]
+/// # struct Runtime;
+/// # impl Runtime {
+/// #     fn is_available() -> Result<(), completest::Unavailable> {
+/// #         Err(completest::Unavailable::new("`zsh` is not installed"))
+/// #     }
+/// # }
+/// completest::skip_if_unavailable!(Runtime::is_available());
+/// unreachable!("zsh is not installed");
+/// ```
+#[macro_export]
+macro_rules! skip_if_unavailable {
+    ($availability:expr) => {
+        $crate::skip_if_unavailable!($availability, return)
+    };
+    ($availability:expr, $skip:expr) => {
+        if let ::std::result::Result::Err(unavailable) = $availability {
+            ::std::eprintln!("skipping: {unavailable}");
+            #[allow(unreachable_code)]
+            $skip;
+        }
+    };
+}
+
 /// Abstract factory for [`Runtime`]
 pub trait RuntimeBuilder: std::fmt::Debug {
     /// The [`Runtime`] being built
@@ -61,16 +491,74 @@ pub trait RuntimeBuilder: std::fmt::Debug {
     /// Name for the runtime (useful for defining a `home`)
     fn name() -> &'static str;
 
+    /// Check whether the shell and anything the runtime depends on is installed
+    fn is_available() -> Result<(), Unavailable> {
+        Ok(())
+    }
+
     /// Initialize a new runtime's home
-    fn new(
+    fn new(bin_root: std::path::PathBuf, home: std::path::PathBuf) -> Result<Self::Runtime, Error>;
+    /// Reuse an existing runtime's home
+    fn with_home(
         bin_root: std::path::PathBuf,
         home: std::path::PathBuf,
-    ) -> std::io::Result<Self::Runtime>;
+    ) -> Result<Self::Runtime, Error>;
+}
+
+/// Dyn-compatible [`RuntimeBuilder`], for looping over runtimes
+///
+/// Implemented for every [`RuntimeBuilder`].
+pub trait RuntimeFactory: std::fmt::Debug {
+    /// Name for the runtime (useful for defining a `home`)
+    fn runtime_name(&self) -> &'static str;
+
+    /// Check whether the shell and anything the runtime depends on is installed
+    fn check_available(&self) -> Result<(), Unavailable>;
+
+    /// Initialize a new runtime's home
+    fn new_runtime(
+        &self,
+        bin_root: std::path::PathBuf,
+        home: std::path::PathBuf,
+    ) -> Result<Box<dyn Runtime>, Error>;
     /// Reuse an existing runtime's home
-    fn with_home(
+    fn runtime_with_home(
+        &self,
         bin_root: std::path::PathBuf,
         home: std::path::PathBuf,
-    ) -> std::io::Result<Self::Runtime>;
+    ) -> Result<Box<dyn Runtime>, Error>;
+}
+
+impl<B> RuntimeFactory for B
+where
+    B: RuntimeBuilder,
+    B::Runtime: 'static,
+{
+    fn runtime_name(&self) -> &'static str {
+        B::name()
+    }
+
+    fn check_available(&self) -> Result<(), Unavailable> {
+        B::is_available()
+    }
+
+    fn new_runtime(
+        &self,
+        bin_root: std::path::PathBuf,
+        home: std::path::PathBuf,
+    ) -> Result<Box<dyn Runtime>, Error> {
+        let runtime = B::new(bin_root, home)?;
+        Ok(Box::new(runtime))
+    }
+
+    fn runtime_with_home(
+        &self,
+        bin_root: std::path::PathBuf,
+        home: std::path::PathBuf,
+    ) -> Result<Box<dyn Runtime>, Error> {
+        let runtime = B::with_home(bin_root, home)?;
+        Ok(Box::new(runtime))
+    }
 }
 
 /// Run completions for a shell
@@ -79,8 +567,135 @@ pub trait Runtime: std::fmt::Debug {
     fn home(&self) -> &std::path::Path;
 
     /// Register a completion script
-    fn register(&mut self, name: &str, content: &str) -> std::io::Result<()>;
+    fn register(&mut self, name: &str, content: &str) -> Result<(), Error>;
 
     /// Get the output from typing `input` into the shell
-    fn complete(&mut self, input: &str, term: &Term) -> std::io::Result<String>;
+    fn complete(&mut self, input: &str, term: &Term) -> Result<String, Error> {
+        self.complete_input(&Input::from(input), term)
+    }
+
+    /// Get the candidates offered by the shell for `input`
+    fn completion(&mut self, input: &str, term: &Term) -> Result<Completion, Error> {
+        self.completion_input(&Input::from(input), term)
+    }
+
+    /// Get the output from typing `input` into the shell, including keys besides `Tab`
+    ///
+    /// Defaults to the [screen][Completion::get_screen] of [`Runtime::completion_input`].
+    fn complete_input(&mut self, input: &Input, term: &Term) -> Result<String, Error> {
+        self.completion_input(input, term)
+            .map(|completion| completion.get_screen().to_owned())
+    }
+
+    /// Get the candidates offered by the shell for `input`, including keys besides `Tab`
+    fn completion_input(&mut self, input: &Input, term: &Term) -> Result<Completion, Error>;
+
+    /// Version of the shell being run
+    fn version(&self) -> Result<Version, Error> {
+        Err(Error::Unsupported("reporting the shell version".to_owned()))
+    }
+}
+
+impl<R: Runtime + ?Sized> Runtime for Box<R> {
+    fn home(&self) -> &std::path::Path {
+        (**self).home()
+    }
+
+    fn register(&mut self, name: &str, content: &str) -> Result<(), Error> {
+        (**self).register(name, content)
+    }
+
+    fn complete(&mut self, input: &str, term: &Term) -> Result<String, Error> {
+        (**self).complete(input, term)
+    }
+
+    fn completion(&mut self, input: &str, term: &Term) -> Result<Completion, Error> {
+        (**self).completion(input, term)
+    }
+
+    fn complete_input(&mut self, input: &Input, term: &Term) -> Result<String, Error> {
+        (**self).complete_input(input, term)
+    }
+
+    fn completion_input(&mut self, input: &Input, term: &Term) -> Result<Completion, Error> {
+        (**self).completion_input(input, term)
+    }
+
+    fn version(&self) -> Result<Version, Error> {
+        (**self).version()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn version_numbers() {
+        assert_eq!(Version::new("5.2.15(1)-release").get_numbers(), [5, 2, 15]);
+        assert_eq!(
+            Version::new("zsh 5.9 (x86_64-pc-linux-gnu)").get_numbers(),
+            [5, 9]
+        );
+        assert_eq!(Version::new("v0.88.1").get_numbers(), [0, 88, 1]);
+        assert_eq!(Version::new("3.7.0-dirty").get_numbers(), [3, 7, 0]);
+        assert_eq!(Version::new("7").get_numbers(), [7]);
+        assert_eq!(Version::new("1.2.").get_numbers(), [1, 2]);
+        assert_eq!(Version::new("unknown").get_numbers(), [] as [u64; 0]);
+        assert_eq!(Version::new("").get_numbers(), [] as [u64; 0]);
+    }
+
+    #[test]
+    fn expected_path_fallback() {
+        let root = std::env::temp_dir().join(format!("completest-expected-{}", std::process::id()));
+        std::fs::create_dir_all(&root).unwrap();
+        let path = root.join("complete.term.svg");
+        let version = Version::new("5.2.15(1)-release");
+
+        assert_eq!(expected_path(&path, &version), path);
+
+        std::fs::write(root.join("complete@5.term.svg"), "").unwrap();
+        assert_eq!(
+            expected_path(&path, &version),
+            root.join("complete@5.term.svg")
+        );
+
+        std::fs::write(root.join("complete@5.2.term.svg"), "").unwrap();
+        assert_eq!(
+            expected_path(&path, &version),
+            root.join("complete@5.2.term.svg")
+        );
+
+        std::fs::write(root.join("complete@5.2.15.term.svg"), "").unwrap();
+        assert_eq!(
+            expected_path(&path, &version),
+            root.join("complete@5.2.15.term.svg")
+        );
+
+        // Other versions are ignored
+        assert_eq!(
+            expected_path(&path, &Version::new("5.1.8")),
+            root.join("complete@5.term.svg")
+        );
+        assert_eq!(expected_path(&path, &Version::new("4.4")), path);
+        assert_eq!(expected_path(&path, &Version::new("unknown")), path);
+
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn expected_path_without_extension() {
+        let root =
+            std::env::temp_dir().join(format!("completest-expected-bare-{}", std::process::id()));
+        std::fs::create_dir_all(&root).unwrap();
+        let path = root.join("complete");
+        std::fs::write(root.join("complete@3"), "").unwrap();
+
+        assert_eq!(
+            expected_path(&path, &Version::new("3.7.0")),
+            root.join("complete@3")
+        );
+
+        std::fs::remove_dir_all(&root).unwrap();
+    }
 }